@@ -0,0 +1,818 @@
+//! Delimited codecs between framed and stream queues
+//!
+//! Useful for sending frames over a byte oriented link, such as a UART. An
+//! [`EncodePump`](crate::codec::EncodePump) reads frames from a framed consumer,
+//! and writes them to a stream producer as a delimited byte stream. A
+//! [`DecodePump`](crate::codec::DecodePump) does the opposite, reading a byte
+//! stream from a stream consumer, and writing each decoded frame to a framed
+//! producer.
+//!
+//! Three formats are provided: [`Cobs`](crate::codec::Cobs),
+//! [`Slip`](crate::codec::Slip) and newline delimited
+//! [`Lines`](crate::codec::Lines). Other formats can be used by implementing
+//! [`Encoder`](crate::codec::Encoder) and [`Decoder`](crate::codec::Decoder).
+
+use crate::{
+    prod_cons::{
+        framed::{FramedConsumer, FramedGrantW, FramedProducer, LenHeader},
+        stream::{StreamConsumer, StreamProducer},
+    },
+    traits::{
+        bbqhdl::BbqHandle,
+        coordination::{ReadGrantError, WriteGrantError},
+        notifier::AsyncNotifier,
+    },
+};
+
+/// Encodes whole frames into a delimited byte stream
+pub trait Encoder {
+    /// The exact number of bytes that `frame` encodes to, including delimiters
+    fn encoded_len(&self, frame: &[u8]) -> usize;
+
+    /// Encode `frame` into `out`, returning the number of bytes written
+    ///
+    /// `out` is at least [`Encoder::encoded_len`] bytes long.
+    fn encode(&mut self, frame: &[u8], out: &mut [u8]) -> usize;
+}
+
+/// Decodes a delimited byte stream into frames, one byte at a time
+///
+/// Decoders may skip empty frames, as [`Slip`] and [`Lines`] do, so an empty
+/// frame sent with those codecs is not received at all. [`Cobs`] decodes empty
+/// frames.
+pub trait Decoder {
+    /// Feed the next byte of the stream to the decoder
+    fn feed(&mut self, byte: u8) -> Decoded;
+}
+
+/// The result of feeding a byte to a [`Decoder`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decoded {
+    /// The byte was consumed without producing any output
+    Nothing,
+    /// The byte decoded to the next byte of the current frame
+    Byte(u8),
+    /// The byte completed the current frame
+    Frame,
+    /// The byte ended a malformed frame, which must be discarded. The decoder
+    /// is ready to decode the next frame.
+    Invalid,
+}
+
+/// Errors associated with running an [`EncodePump`] or [`DecodePump`]
+#[derive(PartialEq, Debug)]
+pub enum CodecError {
+    /// Reading from the source queue failed
+    Read(ReadGrantError),
+    /// Writing to the destination queue failed
+    Write(WriteGrantError),
+}
+
+// ---- Cobs ----
+
+/// Consistent Overhead Byte Stuffing, with each frame followed by a zero byte
+#[derive(Debug, Default, Clone)]
+pub struct Cobs {
+    /// Data bytes left in the current block
+    remaining: u8,
+    /// The code of the current block
+    code: u8,
+    in_frame: bool,
+}
+
+impl Cobs {
+    /// Create a COBS codec, ready to start a new frame
+    pub const fn new() -> Self {
+        Self {
+            remaining: 0,
+            code: 0,
+            in_frame: false,
+        }
+    }
+}
+
+impl Encoder for Cobs {
+    fn encoded_len(&self, frame: &[u8]) -> usize {
+        // One code byte per block, and the delimiter
+        let mut len = frame.len() + 2;
+        let mut run = 0;
+        for &b in frame {
+            if b == 0 {
+                run = 0;
+            } else {
+                run += 1;
+                if run == 254 {
+                    len += 1;
+                    run = 0;
+                }
+            }
+        }
+        len
+    }
+
+    fn encode(&mut self, frame: &[u8], out: &mut [u8]) -> usize {
+        let mut code_idx = 0;
+        let mut code = 1u8;
+        let mut pos = 1;
+        for &b in frame {
+            if b != 0 {
+                out[pos] = b;
+                pos += 1;
+                code += 1;
+            }
+            if b == 0 || code == 0xFF {
+                out[code_idx] = code;
+                code_idx = pos;
+                pos += 1;
+                code = 1;
+            }
+        }
+        out[code_idx] = code;
+        out[pos] = 0;
+        pos + 1
+    }
+}
+
+impl Decoder for Cobs {
+    fn feed(&mut self, byte: u8) -> Decoded {
+        if byte == 0 {
+            let res = if self.remaining != 0 {
+                // The frame ended in the middle of a block
+                Decoded::Invalid
+            } else if self.in_frame {
+                Decoded::Frame
+            } else {
+                Decoded::Nothing
+            };
+            *self = Self::new();
+            return res;
+        }
+
+        if self.remaining != 0 {
+            self.remaining -= 1;
+            return Decoded::Byte(byte);
+        }
+
+        // A new block. Every block except a full one, and the last one, is
+        // followed by a zero.
+        let zero = self.in_frame && self.code != 0xFF;
+        self.code = byte;
+        self.remaining = byte - 1;
+        self.in_frame = true;
+        if zero {
+            Decoded::Byte(0)
+        } else {
+            Decoded::Nothing
+        }
+    }
+}
+
+// ---- Slip ----
+
+/// Serial Line Internet Protocol framing, RFC 1055
+///
+/// Each frame is both preceded and followed by an `END` byte, which discards
+/// any line noise received before the frame. Empty frames are skipped.
+#[derive(Debug, Default, Clone)]
+pub struct Slip {
+    escaped: bool,
+    in_frame: bool,
+    invalid: bool,
+}
+
+impl Slip {
+    const END: u8 = 0xC0;
+    const ESC: u8 = 0xDB;
+    const ESC_END: u8 = 0xDC;
+    const ESC_ESC: u8 = 0xDD;
+
+    /// Create a SLIP codec, ready to start a new frame
+    pub const fn new() -> Self {
+        Self {
+            escaped: false,
+            in_frame: false,
+            invalid: false,
+        }
+    }
+}
+
+impl Encoder for Slip {
+    fn encoded_len(&self, frame: &[u8]) -> usize {
+        let escapes = frame
+            .iter()
+            .filter(|&&b| b == Self::END || b == Self::ESC)
+            .count();
+        frame.len() + escapes + 2
+    }
+
+    fn encode(&mut self, frame: &[u8], out: &mut [u8]) -> usize {
+        out[0] = Self::END;
+        let mut pos = 1;
+        for &b in frame {
+            let esc = match b {
+                Self::END => Self::ESC_END,
+                Self::ESC => Self::ESC_ESC,
+                _ => {
+                    out[pos] = b;
+                    pos += 1;
+                    continue;
+                }
+            };
+            out[pos] = Self::ESC;
+            out[pos + 1] = esc;
+            pos += 2;
+        }
+        out[pos] = Self::END;
+        pos + 1
+    }
+}
+
+impl Decoder for Slip {
+    fn feed(&mut self, byte: u8) -> Decoded {
+        if byte == Self::END {
+            let res = if self.invalid || self.escaped {
+                Decoded::Invalid
+            } else if self.in_frame {
+                Decoded::Frame
+            } else {
+                Decoded::Nothing
+            };
+            *self = Self::new();
+            return res;
+        }
+        if self.invalid {
+            return Decoded::Nothing;
+        }
+
+        self.in_frame = true;
+        if self.escaped {
+            self.escaped = false;
+            match byte {
+                Self::ESC_END => Decoded::Byte(Self::END),
+                Self::ESC_ESC => Decoded::Byte(Self::ESC),
+                _ => {
+                    self.invalid = true;
+                    Decoded::Nothing
+                }
+            }
+        } else if byte == Self::ESC {
+            self.escaped = true;
+            Decoded::Nothing
+        } else {
+            Decoded::Byte(byte)
+        }
+    }
+}
+
+// ---- Lines ----
+
+/// Newline delimited text
+///
+/// Frames are not escaped, so a frame containing a newline is received as
+/// multiple frames. The decoder ignores carriage returns, and skips empty lines.
+#[derive(Debug, Default, Clone)]
+pub struct Lines {
+    in_frame: bool,
+}
+
+impl Lines {
+    /// Create a line codec, ready to start a new line
+    pub const fn new() -> Self {
+        Self { in_frame: false }
+    }
+}
+
+impl Encoder for Lines {
+    fn encoded_len(&self, frame: &[u8]) -> usize {
+        frame.len() + 1
+    }
+
+    fn encode(&mut self, frame: &[u8], out: &mut [u8]) -> usize {
+        out[..frame.len()].copy_from_slice(frame);
+        out[frame.len()] = b'\n';
+        frame.len() + 1
+    }
+}
+
+impl Decoder for Lines {
+    fn feed(&mut self, byte: u8) -> Decoded {
+        match byte {
+            b'\n' if core::mem::take(&mut self.in_frame) => Decoded::Frame,
+            b'\n' | b'\r' => Decoded::Nothing,
+            _ => {
+                self.in_frame = true;
+                Decoded::Byte(byte)
+            }
+        }
+    }
+}
+
+// ---- EncodePump ----
+
+/// Encodes frames from a framed queue into a stream queue
+pub struct EncodePump<E, QF, QS, H = u16>
+where
+    E: Encoder,
+    QF: BbqHandle,
+    QS: BbqHandle,
+    H: LenHeader,
+{
+    codec: E,
+    frames: FramedConsumer<QF, H>,
+    bytes: StreamProducer<QS>,
+}
+
+impl<E, QF, QS, H> EncodePump<E, QF, QS, H>
+where
+    E: Encoder,
+    QF: BbqHandle,
+    QS: BbqHandle,
+    H: LenHeader,
+{
+    /// Create a pump reading from `frames`, and writing to `bytes`
+    pub fn new(codec: E, frames: FramedConsumer<QF, H>, bytes: StreamProducer<QS>) -> Self {
+        Self {
+            codec,
+            frames,
+            bytes,
+        }
+    }
+
+    /// Obtain the codec and handles of the pump
+    pub fn into_inner(self) -> (E, FramedConsumer<QF, H>, StreamProducer<QS>) {
+        (self.codec, self.frames, self.bytes)
+    }
+
+    /// Encode as many frames as currently fit into the byte stream
+    ///
+    /// Returns the number of frames encoded. Each frame is encoded into a single
+    /// grant, so it is kept in the framed queue until its encoding fits. A frame
+    /// whose encoding is larger than the stream buffer is discarded, and
+    /// [`WriteGrantError::TooLarge`] is returned.
+    pub fn pump(&mut self) -> Result<usize, CodecError> {
+        let mut frames = 0;
+        loop {
+            let rgr = match self.frames.read() {
+                Ok(rgr) => rgr,
+                Err(ReadGrantError::Empty | ReadGrantError::GrantInProgress) => return Ok(frames),
+                Err(e) => return Err(CodecError::Read(e)),
+            };
+            let len = self.codec.encoded_len(&rgr);
+            let mut wgr = match self.bytes.grant_exact(len) {
+                Ok(wgr) => wgr,
+                Err(e) => {
+                    rgr.grant_failed(e).map_err(CodecError::Write)?;
+                    return Ok(frames);
+                }
+            };
+            let used = self.codec.encode(&rgr, &mut wgr);
+            wgr.commit(used);
+            rgr.release();
+            frames += 1;
+        }
+    }
+}
+
+impl<E, QF, QS, H> EncodePump<E, QF, QS, H>
+where
+    E: Encoder,
+    QF: BbqHandle,
+    QF::Notifier: AsyncNotifier,
+    QS: BbqHandle,
+    QS::Notifier: AsyncNotifier,
+    H: LenHeader,
+{
+    /// Encode frames as they become available, until an error occurs
+    ///
+    /// This returns once either queue is closed. As with [`EncodePump::pump`], a
+    /// frame whose encoding can never fit is discarded, and the error returned.
+    pub async fn run(&mut self) -> CodecError {
+        loop {
+            let rgr = match self.frames.wait_read().await {
+                Ok(rgr) => rgr,
+                Err(e) => return CodecError::Read(e),
+            };
+            let len = self.codec.encoded_len(&rgr);
+            let mut wgr = match self.bytes.wait_grant_exact(len).await {
+                Ok(wgr) => wgr,
+                Err(e) => match rgr.grant_failed(e) {
+                    Ok(()) => continue,
+                    Err(e) => return CodecError::Write(e),
+                },
+            };
+            let used = self.codec.encode(&rgr, &mut wgr);
+            wgr.commit(used);
+            rgr.release();
+        }
+    }
+}
+
+// ---- DecodePump ----
+
+/// Decodes frames from a stream queue into a framed queue
+///
+/// Each frame is decoded directly into a grant of `max_frame` bytes of the
+/// framed queue, which is obtained when the frame starts. Decoding stops until
+/// the consumer of the framed queue has made room for that grant. Only frames
+/// longer than `max_frame`, and malformed frames, are discarded and counted, see
+/// [`DecodePump::discarded`].
+pub struct DecodePump<D, QS, QF, H = u16>
+where
+    D: Decoder,
+    QS: BbqHandle,
+    QF: BbqHandle,
+    H: LenHeader,
+{
+    codec: D,
+    bytes: StreamConsumer<QS>,
+    frames: FramedProducer<QF, H>,
+    max_frame: H,
+    grant: Option<FramedGrantW<QF, H>>,
+    used: usize,
+    overrun: bool,
+    discarded: usize,
+}
+
+impl<D, QS, QF, H> DecodePump<D, QS, QF, H>
+where
+    D: Decoder,
+    QS: BbqHandle,
+    QF: BbqHandle,
+    H: LenHeader,
+{
+    /// Create a pump reading from `bytes`, and writing frames of up to
+    /// `max_frame` bytes to `frames`
+    pub fn new(
+        codec: D,
+        bytes: StreamConsumer<QS>,
+        frames: FramedProducer<QF, H>,
+        max_frame: H,
+    ) -> Self {
+        Self {
+            codec,
+            bytes,
+            frames,
+            max_frame,
+            grant: None,
+            used: 0,
+            overrun: false,
+            discarded: 0,
+        }
+    }
+
+    /// Obtain the codec and handles of the pump
+    ///
+    /// Any partially decoded frame is discarded.
+    pub fn into_inner(self) -> (D, StreamConsumer<QS>, FramedProducer<QF, H>) {
+        (self.codec, self.bytes, self.frames)
+    }
+
+    /// The number of frames discarded, because they were malformed, or too long
+    pub fn discarded(&self) -> usize {
+        self.discarded
+    }
+
+    /// Decode all bytes currently in the stream, as long as there is room for frames
+    ///
+    /// Returns the number of frames completed. Bytes are left in the stream
+    /// queue when the framed queue has no room for the next frame yet. Returns
+    /// [`WriteGrantError::TooLarge`] if a frame of `max_frame` bytes can never
+    /// fit in the framed queue.
+    pub fn pump(&mut self) -> Result<usize, CodecError> {
+        let mut frames = 0;
+        loop {
+            let rgr = match self.bytes.read() {
+                Ok(rgr) => rgr,
+                Err(ReadGrantError::Empty | ReadGrantError::GrantInProgress) => return Ok(frames),
+                Err(e) => return Err(CodecError::Read(e)),
+            };
+            let used = self.decode(&rgr, &mut frames)?;
+            let done = used == rgr.len();
+            rgr.release(used);
+            if !done {
+                return Ok(frames);
+            }
+        }
+    }
+
+    /// Decode `data`, returning the number of bytes consumed
+    ///
+    /// Stops early if there is no room for the next frame yet.
+    fn decode(&mut self, data: &[u8], frames: &mut usize) -> Result<usize, CodecError> {
+        for (i, &byte) in data.iter().enumerate() {
+            if self.grant.is_none() && !self.overrun {
+                match self.frames.grant(self.max_frame) {
+                    Ok(grant) => self.grant = Some(grant),
+                    Err(
+                        WriteGrantError::InsufficientSize
+                        | WriteGrantError::WouldWrap
+                        | WriteGrantError::GrantInProgress,
+                    ) => return Ok(i),
+                    Err(e) => return Err(CodecError::Write(e)),
+                }
+            }
+
+            match self.codec.feed(byte) {
+                Decoded::Nothing => {}
+                Decoded::Byte(b) => match self.grant.as_mut().and_then(|g| g.get_mut(self.used)) {
+                    Some(dst) => {
+                        *dst = b;
+                        self.used += 1;
+                    }
+                    None => {
+                        // Longer than `max_frame`, skip the rest of the frame
+                        self.grant = None;
+                        self.overrun = true;
+                    }
+                },
+                Decoded::Frame if !self.overrun => {
+                    if let Some(grant) = self.grant.take() {
+                        grant.commit_len(self.used);
+                    }
+                    self.used = 0;
+                    *frames += 1;
+                }
+                Decoded::Frame | Decoded::Invalid => {
+                    // Give the space back, the next frame starts with a new grant
+                    self.grant = None;
+                    self.used = 0;
+                    self.overrun = false;
+                    self.discarded += 1;
+                }
+            }
+        }
+        Ok(data.len())
+    }
+}
+
+impl<D, QS, QF, H> DecodePump<D, QS, QF, H>
+where
+    D: Decoder,
+    QS: BbqHandle,
+    QS::Notifier: AsyncNotifier,
+    QF: BbqHandle,
+    QF::Notifier: AsyncNotifier,
+    H: LenHeader,
+{
+    /// Decode frames as bytes become available, until an error occurs
+    ///
+    /// This returns once either queue is closed, or as with
+    /// [`DecodePump::pump`], if a frame of `max_frame` bytes can never fit.
+    pub async fn run(&mut self) -> CodecError {
+        loop {
+            let rgr = match self.bytes.wait_read().await {
+                Ok(rgr) => rgr,
+                Err(e) => return CodecError::Read(e),
+            };
+            let used = match self.decode(&rgr, &mut 0) {
+                Ok(used) => used,
+                Err(e) => return e,
+            };
+            let stalled = used < rgr.len();
+            rgr.release(used);
+            if stalled {
+                // Wait for the consumer of the framed queue to make room
+                match self.frames.wait_grant(self.max_frame).await {
+                    Ok(grant) => self.grant = Some(grant),
+                    Err(e) => return CodecError::Write(e),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, target_has_atomic = "ptr"))]
+mod test {
+    use core::{ops::Deref, time::Duration};
+
+    use super::*;
+    use crate::{
+        queue::BBQueue,
+        traits::{
+            coordination::cas::AtomicCoord,
+            notifier::{blocking::Blocking, maitake::MaiNotSpsc},
+            storage::Inline,
+        },
+    };
+
+    type Queue = BBQueue<Inline<64>, AtomicCoord, Blocking>;
+
+    fn roundtrip<C: Encoder + Decoder + Clone>(codec: C, frames: &[&[u8]]) {
+        let (tx, wire, rx) = (Queue::new(), Queue::new(), Queue::new());
+        let mut enc = EncodePump::new(codec.clone(), tx.framed_consumer(), wire.stream_producer());
+        let mut dec = DecodePump::new(codec, wire.stream_consumer(), rx.framed_producer(), 40);
+        let prod = tx.framed_producer();
+        let cons = rx.framed_consumer();
+
+        for frame in frames {
+            prod.push_frame(frame).unwrap();
+            assert_eq!(enc.pump(), Ok(1));
+            assert_eq!(dec.pump(), Ok(1));
+            let rgr = cons.read().unwrap();
+            assert_eq!(&*rgr, *frame);
+            rgr.release();
+        }
+        assert_eq!(dec.discarded(), 0);
+    }
+
+    #[test]
+    fn roundtrips() {
+        let long: [u8; 40] = core::array::from_fn(|i| i as u8 + 1);
+        let frames: &[&[u8]] = &[&[1, 2, 3], &[0, 0xC0, 0xDB, 0], &long, &[0; 5]];
+        roundtrip(Cobs::new(), frames);
+        roundtrip(Cobs::new(), &[&[], &[1], &[]]);
+        roundtrip(Slip::new(), frames);
+        roundtrip(Lines::new(), &[b"hello", b"a b c"]);
+    }
+
+    #[test]
+    fn encodings() {
+        let mut out = [0; 600];
+        let mut cobs = Cobs::new();
+        let n = cobs.encode(&[0x11, 0x00, 0x22], &mut out);
+        assert_eq!(&out[..n], &[0x02, 0x11, 0x02, 0x22, 0x00]);
+
+        // Runs of 254 non-zero bytes need an extra code byte
+        let long = [0x01; 300];
+        assert_eq!(cobs.encoded_len(&long), 300 + 3);
+        assert_eq!(cobs.encode(&long, &mut out), 300 + 3);
+
+        let mut slip = Slip::new();
+        let n = slip.encode(&[0xC0, 0x01, 0xDB], &mut out);
+        assert_eq!(&out[..n], &[0xC0, 0xDB, 0xDC, 0x01, 0xDB, 0xDD, 0xC0]);
+        assert_eq!(slip.encoded_len(&[0xC0, 0x01, 0xDB]), n);
+    }
+
+    #[test]
+    fn decode_recovery() {
+        let (wire, rx) = (Queue::new(), Queue::new());
+        let prod = wire.stream_producer();
+        let mut dec = DecodePump::new(
+            Cobs::new(),
+            wire.stream_consumer(),
+            rx.framed_producer(),
+            16,
+        );
+        let cons = rx.framed_consumer();
+
+        // A truncated frame, then a valid one
+        prod.write_all(&[0x05, 0x01, 0x00, 0x03, 0x03, 0x04, 0x00])
+            .unwrap();
+        assert_eq!(dec.pump(), Ok(1));
+        assert_eq!(dec.discarded(), 1);
+        assert_eq!(&*cons.read().unwrap(), &[0x03, 0x04]);
+
+        // Frames of `max_frame` bytes must fit in the framed queue
+        let (prod, cons) = (wire.stream_producer(), wire.stream_consumer());
+        let mut dec = DecodePump::new(Cobs::new(), cons, rx.framed_producer(), 63);
+        prod.write_all(&[0x02, 0x01, 0x00]).unwrap();
+        assert_eq!(
+            dec.pump(),
+            Err(CodecError::Write(WriteGrantError::TooLarge))
+        );
+    }
+
+    #[test]
+    fn decode_backpressure() {
+        let (wire, rx) = (
+            Queue::new(),
+            BBQueue::<Inline<32>, AtomicCoord, Blocking>::new(),
+        );
+        let prod = wire.stream_producer();
+        let mut dec = DecodePump::new(Cobs::new(), wire.stream_consumer(), rx.framed_producer(), 8);
+        let cons = rx.framed_consumer();
+        let mut enc = Cobs::new();
+        let mut push = |frame: &[u8]| {
+            let mut out = [0; 64];
+            let n = enc.encode(frame, &mut out);
+            prod.write_all(&out[..n]).unwrap();
+        };
+
+        // Six short frames fit, but then there is no room for a grant of
+        // `max_frame` bytes
+        for i in 0..7 {
+            push(&[i, i]);
+        }
+        assert_eq!(dec.pump(), Ok(6));
+        assert_eq!(dec.pump(), Ok(0));
+
+        // A malformed frame, while the queue is still full
+        prod.write_all(&[0x05, 0x01, 0x00]).unwrap();
+        push(&[7, 7]);
+
+        // Once the consumer catches up, nothing else is lost
+        for i in 0..6 {
+            let rgr = cons.read().unwrap();
+            assert_eq!(&*rgr, &[i, i]);
+            rgr.release();
+        }
+        assert_eq!(dec.pump(), Ok(2));
+        assert_eq!(dec.discarded(), 1);
+        for i in 6..8 {
+            let rgr = cons.read().unwrap();
+            assert_eq!(&*rgr, &[i, i]);
+            rgr.release();
+        }
+
+        // Frames longer than `max_frame` are discarded
+        push(&[0xBB; 9]);
+        push(&[8; 8]);
+        assert_eq!(dec.pump(), Ok(1));
+        assert_eq!(dec.discarded(), 2);
+        assert_eq!(&*cons.read().unwrap(), &[8; 8]);
+    }
+
+    #[test]
+    fn encode_large_frames() {
+        // Each encoded frame takes more than half of the stream queue
+        let (tx, wire) = (
+            BBQueue::<Inline<256>, AtomicCoord, Blocking>::new(),
+            Queue::new(),
+        );
+        let prod = tx.framed_producer();
+        let mut enc = EncodePump::new(Cobs::new(), tx.framed_consumer(), wire.stream_producer());
+        let cons = wire.stream_consumer();
+
+        for i in 1..=3 {
+            prod.push_frame(&[i; 38]).unwrap();
+        }
+        for i in 1..=3 {
+            assert_eq!(enc.pump(), Ok(1));
+            assert_eq!(enc.pump(), Ok(0));
+            let rgr = cons.read().unwrap();
+            assert_eq!((rgr.len(), rgr[1]), (40, i));
+            rgr.release(40);
+        }
+        assert_eq!(enc.pump(), Ok(0));
+    }
+
+    #[tokio::test]
+    async fn run_large_frames() {
+        use crate::traits::notifier::maitake::MaiNotSpsc;
+
+        let tx = BBQueue::<Inline<256>, AtomicCoord, MaiNotSpsc>::new();
+        let wire = BBQueue::<Inline<64>, AtomicCoord, MaiNotSpsc>::new();
+        let prod = tx.framed_producer();
+        let mut enc = EncodePump::new(Cobs::new(), tx.framed_consumer(), wire.stream_producer());
+        let cons = wire.stream_consumer();
+
+        for i in 1..=3 {
+            prod.push_frame(&[i; 38]).unwrap();
+        }
+        let drain = async {
+            for i in 1..=3 {
+                let rgr = cons.wait_read().await.unwrap();
+                assert_eq!((rgr.len(), rgr[1]), (40, i));
+                rgr.release(40);
+            }
+            prod.close();
+        };
+        let run = tokio::time::timeout(core::time::Duration::from_secs(1), enc.run());
+        let (res, ()) = tokio::join!(run, drain);
+        assert_eq!(res.unwrap(), CodecError::Read(ReadGrantError::Closed));
+    }
+
+    #[tokio::test]
+    async fn codec_backpressure() {
+        use crate::{
+            codec::{Cobs, CodecError, DecodePump, Encoder},
+            prod_cons::framed::FramedConsumer,
+            traits::coordination::ReadGrantError,
+        };
+
+        static WIRE: BBQueue<Inline<256>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        static RX: BBQueue<Inline<32>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        let frames: Vec<Vec<u8>> = (0..20u8).map(|i| vec![i + 1; 10]).collect();
+
+        // Far more data than fits in the framed queue at once
+        let prod = WIRE.stream_producer();
+        let mut cobs = Cobs::new();
+        for frame in &frames {
+            let mut out = [0u8; 16];
+            let n = cobs.encode(frame, &mut out);
+            prod.write_all(&out[..n]).unwrap();
+        }
+        prod.close();
+
+        // A slow consumer must not cause frames to be lost
+        let expected = frames.clone();
+        let rxfut = tokio::task::spawn(async move {
+            let cons: FramedConsumer<_> = RX.framed_consumer();
+            for frame in expected {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                let rgr = cons.wait_read().await.unwrap();
+                assert_eq!(rgr.deref(), frame.as_slice());
+                rgr.release();
+            }
+        });
+        let mut dec = DecodePump::new(
+            Cobs::new(),
+            WIRE.stream_consumer(),
+            RX.framed_producer(),
+            10,
+        );
+        assert_eq!(dec.run().await, CodecError::Read(ReadGrantError::Closed));
+        rxfut.await.unwrap();
+        assert_eq!(dec.discarded(), 0);
+    }
+}
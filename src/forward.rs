@@ -0,0 +1,564 @@
+//! Forwarding between queues
+//!
+//! Useful for chaining queues between the stages of a pipeline, without copying
+//! the data by hand:
+//!
+//! * [`forward`](crate::forward::forward) and
+//!   [`wait_forward`](crate::forward::wait_forward) move bytes from one stream
+//!   queue to another.
+//! * [`forward_frames`](crate::forward::forward_frames) and
+//!   [`wait_forward_frames`](crate::forward::wait_forward_frames) move frames
+//!   from one framed queue to another, keeping the frame boundaries.
+//! * A [`Tee`](crate::forward::Tee) moves frames to a primary queue, and copies
+//!   them to a secondary queue on a best effort basis, e.g. for capturing the
+//!   traffic of a link while debugging.
+//!
+//! Data is only released from the source once it has been committed to the
+//! destination, so a full destination holds the data back in the source.
+
+use crate::{
+    prod_cons::{
+        framed::{FramedConsumer, FramedGrantR, FramedGrantW, FramedProducer, LenHeader},
+        stream::{StreamConsumer, StreamProducer},
+    },
+    traits::{
+        bbqhdl::BbqHandle,
+        coordination::{ReadGrantError, WriteGrantError},
+        notifier::AsyncNotifier,
+    },
+};
+
+/// Errors associated with forwarding data between queues
+#[derive(PartialEq, Debug)]
+pub enum ForwardError {
+    /// Reading from the source queue failed
+    Read(ReadGrantError),
+    /// Writing to the destination queue failed
+    Write(WriteGrantError),
+}
+
+/// Move as many bytes as currently fit from `src` to `dst`
+///
+/// Returns the number of bytes moved.
+pub fn forward<QS, QD>(
+    src: &StreamConsumer<QS>,
+    dst: &StreamProducer<QD>,
+) -> Result<usize, ForwardError>
+where
+    QS: BbqHandle,
+    QD: BbqHandle,
+{
+    let mut moved = 0;
+    loop {
+        let rgr = match src.read() {
+            Ok(rgr) => rgr,
+            Err(ReadGrantError::Empty | ReadGrantError::GrantInProgress) => return Ok(moved),
+            Err(e) => return Err(ForwardError::Read(e)),
+        };
+        let mut wgr = match dst.grant_max_remaining(rgr.len()) {
+            Ok(wgr) => wgr,
+            Err(WriteGrantError::InsufficientSize | WriteGrantError::GrantInProgress) => {
+                return Ok(moved);
+            }
+            Err(e) => return Err(ForwardError::Write(e)),
+        };
+        let used = wgr.len();
+        wgr.copy_from_slice(&rgr[..used]);
+        wgr.commit(used);
+        rgr.release(used);
+        moved += used;
+    }
+}
+
+/// Move bytes from `src` to `dst` as they become available
+///
+/// Returns `Ok(())` once `src` has been closed and drained. The destination is
+/// not closed, so that several sources may be forwarded in turn.
+pub async fn wait_forward<QS, QD>(
+    src: &StreamConsumer<QS>,
+    dst: &StreamProducer<QD>,
+) -> Result<(), ForwardError>
+where
+    QS: BbqHandle,
+    QS::Notifier: AsyncNotifier,
+    QD: BbqHandle,
+    QD::Notifier: AsyncNotifier,
+{
+    loop {
+        let rgr = match src.wait_read().await {
+            Ok(rgr) => rgr,
+            Err(ReadGrantError::Closed) => return Ok(()),
+            Err(e) => return Err(ForwardError::Read(e)),
+        };
+        let mut wgr = dst
+            .wait_grant_max_remaining(rgr.len())
+            .await
+            .map_err(ForwardError::Write)?;
+        let used = wgr.len();
+        wgr.copy_from_slice(&rgr[..used]);
+        wgr.commit(used);
+        rgr.release(used);
+    }
+}
+
+/// Copy a frame into a grant of the same length, and release it
+fn move_frame<QS, QD, H>(rgr: FramedGrantR<QS, H>, mut wgr: FramedGrantW<QD, H>, len: H)
+where
+    QS: BbqHandle,
+    QD: BbqHandle,
+    H: LenHeader,
+{
+    wgr.copy_from_slice(&rgr);
+    wgr.commit(len);
+    rgr.release();
+}
+
+/// The length of a frame, as a header value
+fn frame_len<Q, H>(rgr: &FramedGrantR<Q, H>) -> H
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    // The frame was written with the same header type, so this always fits
+    H::try_from(rgr.len()).unwrap_or(H::MAX)
+}
+
+/// Move as many frames as currently fit from `src` to `dst`
+///
+/// Returns the number of frames moved. Each frame is kept in `src` until it fits
+/// in `dst`. A frame that is larger than the buffer of `dst` is discarded, and
+/// [`WriteGrantError::TooLarge`] is returned.
+pub fn forward_frames<QS, QD, H>(
+    src: &FramedConsumer<QS, H>,
+    dst: &FramedProducer<QD, H>,
+) -> Result<usize, ForwardError>
+where
+    QS: BbqHandle,
+    QD: BbqHandle,
+    H: LenHeader,
+{
+    let mut frames = 0;
+    loop {
+        let rgr = match src.read() {
+            Ok(rgr) => rgr,
+            Err(ReadGrantError::Empty | ReadGrantError::GrantInProgress) => return Ok(frames),
+            Err(e) => return Err(ForwardError::Read(e)),
+        };
+        let len = frame_len(&rgr);
+        let wgr = match dst.grant(len) {
+            Ok(wgr) => wgr,
+            Err(e) => {
+                rgr.grant_failed(e).map_err(ForwardError::Write)?;
+                return Ok(frames);
+            }
+        };
+        move_frame(rgr, wgr, len);
+        frames += 1;
+    }
+}
+
+/// Move frames from `src` to `dst` as they become available
+///
+/// Returns `Ok(())` once `src` has been closed and drained. The destination is
+/// not closed, so that several sources may be forwarded in turn. As with
+/// [`forward_frames`], a frame that can never fit in `dst` is discarded, and the
+/// error returned.
+pub async fn wait_forward_frames<QS, QD, H>(
+    src: &FramedConsumer<QS, H>,
+    dst: &FramedProducer<QD, H>,
+) -> Result<(), ForwardError>
+where
+    QS: BbqHandle,
+    QS::Notifier: AsyncNotifier,
+    QD: BbqHandle,
+    QD::Notifier: AsyncNotifier,
+    H: LenHeader,
+{
+    loop {
+        let rgr = match src.wait_read().await {
+            Ok(rgr) => rgr,
+            Err(ReadGrantError::Closed) => return Ok(()),
+            Err(e) => return Err(ForwardError::Read(e)),
+        };
+        let len = frame_len(&rgr);
+        let wgr = match dst.wait_grant(len).await {
+            Ok(wgr) => wgr,
+            Err(e) => match rgr.grant_failed(e) {
+                Ok(()) => continue,
+                Err(e) => return Err(ForwardError::Write(e)),
+            },
+        };
+        move_frame(rgr, wgr, len);
+    }
+}
+
+// ---- Tee ----
+
+/// Moves frames to a primary queue, copying them to a secondary queue if possible
+///
+/// The primary queue applies backpressure as with [`forward_frames`]. The copy
+/// to the secondary queue never waits: frames that don't fit there are skipped,
+/// and counted, see [`Tee::dropped`].
+pub struct Tee<QS, QP, QC, H = u16>
+where
+    QS: BbqHandle,
+    QP: BbqHandle,
+    QC: BbqHandle,
+    H: LenHeader,
+{
+    src: FramedConsumer<QS, H>,
+    primary: FramedProducer<QP, H>,
+    secondary: FramedProducer<QC, H>,
+    dropped: usize,
+}
+
+impl<QS, QP, QC, H> Tee<QS, QP, QC, H>
+where
+    QS: BbqHandle,
+    QP: BbqHandle,
+    QC: BbqHandle,
+    H: LenHeader,
+{
+    /// Create a tee reading from `src`, and writing to `primary` and `secondary`
+    pub fn new(
+        src: FramedConsumer<QS, H>,
+        primary: FramedProducer<QP, H>,
+        secondary: FramedProducer<QC, H>,
+    ) -> Self {
+        Self {
+            src,
+            primary,
+            secondary,
+            dropped: 0,
+        }
+    }
+
+    /// Obtain the handles of the tee
+    pub fn into_inner(
+        self,
+    ) -> (
+        FramedConsumer<QS, H>,
+        FramedProducer<QP, H>,
+        FramedProducer<QC, H>,
+    ) {
+        (self.src, self.primary, self.secondary)
+    }
+
+    /// The number of frames that could not be copied to the secondary queue
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Copy a frame to the secondary queue, then move it to the primary queue
+    fn tee_frame(&mut self, rgr: FramedGrantR<QS, H>, wgr: FramedGrantW<QP, H>, len: H) {
+        if self.secondary.push_frame(&rgr).is_err() {
+            self.dropped = self.dropped.wrapping_add(1);
+        }
+        move_frame(rgr, wgr, len);
+    }
+
+    /// Move as many frames as currently fit in the primary queue
+    ///
+    /// Returns the number of frames moved. Errors refer to the source and
+    /// primary queues only, see [`forward_frames`].
+    pub fn pump(&mut self) -> Result<usize, ForwardError> {
+        let mut frames = 0;
+        loop {
+            let rgr = match self.src.read() {
+                Ok(rgr) => rgr,
+                Err(ReadGrantError::Empty | ReadGrantError::GrantInProgress) => return Ok(frames),
+                Err(e) => return Err(ForwardError::Read(e)),
+            };
+            let len = frame_len(&rgr);
+            let wgr = match self.primary.grant(len) {
+                Ok(wgr) => wgr,
+                Err(e) => {
+                    rgr.grant_failed(e).map_err(ForwardError::Write)?;
+                    return Ok(frames);
+                }
+            };
+            self.tee_frame(rgr, wgr, len);
+            frames += 1;
+        }
+    }
+}
+
+impl<QS, QP, QC, H> Tee<QS, QP, QC, H>
+where
+    QS: BbqHandle,
+    QS::Notifier: AsyncNotifier,
+    QP: BbqHandle,
+    QP::Notifier: AsyncNotifier,
+    QC: BbqHandle,
+    H: LenHeader,
+{
+    /// Move frames as they become available, until the source is closed
+    ///
+    /// Returns `Ok(())` once the source has been closed and drained. Only the
+    /// primary queue is waited on.
+    pub async fn run(&mut self) -> Result<(), ForwardError> {
+        loop {
+            let rgr = match self.src.wait_read().await {
+                Ok(rgr) => rgr,
+                Err(ReadGrantError::Closed) => return Ok(()),
+                Err(e) => return Err(ForwardError::Read(e)),
+            };
+            let len = frame_len(&rgr);
+            let wgr = match self.primary.wait_grant(len).await {
+                Ok(wgr) => wgr,
+                Err(e) => match rgr.grant_failed(e) {
+                    Ok(()) => continue,
+                    Err(e) => return Err(ForwardError::Write(e)),
+                },
+            };
+            self.tee_frame(rgr, wgr, len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::time::Duration;
+
+    use super::*;
+    use crate::{
+        queue::BBQueue,
+        traits::{
+            coordination::cas::AtomicCoord,
+            notifier::{blocking::Blocking, maitake::MaiNotSpsc},
+            storage::Inline,
+        },
+    };
+
+    type Queue<const N: usize> = BBQueue<Inline<N>, AtomicCoord, Blocking>;
+
+    #[test]
+    fn forward_stream() {
+        let (a, b) = (Queue::<16>::new(), Queue::<8>::new());
+        let (src, dst) = (a.stream_consumer(), b.stream_producer());
+        let prod = a.stream_producer();
+        let cons = b.stream_consumer();
+
+        // Only as much as fits is moved, the rest stays in the source
+        assert_eq!(prod.push(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]), 10);
+        assert_eq!(forward(&src, &dst), Ok(8));
+        assert_eq!(src.len(), 2);
+        let mut buf = [0; 8];
+        assert_eq!(cons.pop_into(&mut buf), 8);
+        assert_eq!(buf, [1, 2, 3, 4, 5, 6, 7, 8]);
+
+        assert_eq!(forward(&src, &dst), Ok(2));
+        assert_eq!(forward(&src, &dst), Ok(0));
+        assert_eq!(cons.pop_into(&mut buf), 2);
+        assert_eq!(&buf[..2], &[9, 10]);
+
+        dst.close();
+        prod.push(&[1]);
+        assert_eq!(
+            forward(&src, &dst),
+            Err(ForwardError::Write(WriteGrantError::Closed))
+        );
+    }
+
+    #[test]
+    fn forward_framed() {
+        let (a, b) = (Queue::<32>::new(), Queue::<12>::new());
+        let src: FramedConsumer<_> = a.framed_consumer();
+        let dst: FramedProducer<_> = b.framed_producer();
+        let prod: FramedProducer<_> = a.framed_producer();
+        let cons: FramedConsumer<_> = b.framed_consumer();
+
+        // Frames are kept whole, and held back until they fit
+        prod.push_frame(&[1, 2, 3]).unwrap();
+        prod.push_frame(&[4, 5, 6, 7]).unwrap();
+        prod.push_frame(&[8]).unwrap();
+        assert_eq!(forward_frames(&src, &dst), Ok(2));
+        let mut buf = [0; 8];
+        assert_eq!(cons.pop_frame_into(&mut buf), Ok(3));
+        assert_eq!(cons.pop_frame_into(&mut buf), Ok(4));
+        assert_eq!(&buf[..4], &[4, 5, 6, 7]);
+        assert_eq!(forward_frames(&src, &dst), Ok(1));
+        assert_eq!(cons.pop_frame_into(&mut buf), Ok(1));
+        assert_eq!(buf[0], 8);
+
+        // Frames that can never fit are discarded
+        prod.push_frame(&[0; 16]).unwrap();
+        prod.push_frame(&[9]).unwrap();
+        assert_eq!(
+            forward_frames(&src, &dst),
+            Err(ForwardError::Write(WriteGrantError::TooLarge))
+        );
+        assert_eq!(forward_frames(&src, &dst), Ok(1));
+        assert_eq!(cons.pop_frame_into(&mut buf), Ok(1));
+        assert_eq!(buf[0], 9);
+    }
+
+    #[test]
+    fn tee() {
+        let (a, b, c) = (Queue::<32>::new(), Queue::<32>::new(), Queue::<8>::new());
+        let prod: FramedProducer<_> = a.framed_producer();
+        let primary: FramedConsumer<_> = b.framed_consumer();
+        let secondary: FramedConsumer<_> = c.framed_consumer();
+        let mut tee = Tee::new(
+            a.framed_consumer(),
+            b.framed_producer(),
+            c.framed_producer(),
+        );
+
+        // The secondary queue only has room for the first frame
+        prod.push_frame(&[1, 2, 3]).unwrap();
+        prod.push_frame(&[4, 5, 6]).unwrap();
+        assert_eq!(tee.pump(), Ok(2));
+        assert_eq!(tee.dropped(), 1);
+
+        let mut buf = [0; 8];
+        assert_eq!(primary.pop_frame_into(&mut buf), Ok(3));
+        assert_eq!(&buf[..3], &[1, 2, 3]);
+        assert_eq!(primary.pop_frame_into(&mut buf), Ok(3));
+        assert_eq!(&buf[..3], &[4, 5, 6]);
+        assert_eq!(secondary.pop_frame_into(&mut buf), Ok(3));
+        assert_eq!(&buf[..3], &[1, 2, 3]);
+        assert_eq!(
+            secondary.pop_frame_into(&mut buf),
+            Err(ReadGrantError::Empty)
+        );
+
+        // A closed secondary queue does not stop the primary one
+        secondary.close();
+        prod.push_frame(&[7]).unwrap();
+        assert_eq!(tee.pump(), Ok(1));
+        assert_eq!(tee.dropped(), 2);
+        assert_eq!(primary.pop_frame_into(&mut buf), Ok(1));
+    }
+
+    #[tokio::test]
+    async fn wait_large_frames() {
+        use crate::traits::notifier::maitake::MaiNotSpsc;
+        type Async<const N: usize> = BBQueue<Inline<N>, AtomicCoord, MaiNotSpsc>;
+
+        // Each frame takes more than half of the destination queues
+        let (a, b) = (Async::<256>::new(), Async::<64>::new());
+        let prod: FramedProducer<_> = a.framed_producer();
+        let cons: FramedConsumer<_> = b.framed_consumer();
+        let (src, dst) = (a.framed_consumer(), b.framed_producer());
+
+        let secs = Duration::from_secs(1);
+        for i in 1..=3 {
+            prod.push_frame(&[i; 38]).unwrap();
+        }
+        let drain = async {
+            for i in 1..=3 {
+                let rgr = cons.wait_read().await.unwrap();
+                assert_eq!(&*rgr, &[i; 38]);
+                rgr.release();
+            }
+            prod.close();
+        };
+        let (res, ()) = tokio::join!(
+            tokio::time::timeout(secs, wait_forward_frames(&src, &dst)),
+            drain
+        );
+        assert_eq!(res.unwrap(), Ok(()));
+
+        // The same for the primary queue of a tee
+        let (a, b, c) = (Async::<256>::new(), Async::<64>::new(), Async::<8>::new());
+        let prod: FramedProducer<_> = a.framed_producer();
+        let primary: FramedConsumer<_> = b.framed_consumer();
+        let mut tee = Tee::new(
+            a.framed_consumer(),
+            b.framed_producer(),
+            c.framed_producer(),
+        );
+        for i in 1..=3 {
+            prod.push_frame(&[i; 38]).unwrap();
+        }
+        let drain = async {
+            for i in 1..=3 {
+                let rgr = primary.wait_read().await.unwrap();
+                assert_eq!(&*rgr, &[i; 38]);
+                rgr.release();
+            }
+            prod.close();
+        };
+        let (res, ()) = tokio::join!(tokio::time::timeout(secs, tee.run()), drain);
+        assert_eq!(res.unwrap(), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn forwarding() {
+        use crate::{
+            forward::{Tee, wait_forward, wait_forward_frames},
+            prod_cons::framed::{FramedConsumer, FramedProducer},
+        };
+
+        let msg: Vec<u8> = (0..100).collect();
+
+        // Stream to stream, through a queue smaller than the message
+        static SRC: BBQueue<Inline<16>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        static DST: BBQueue<Inline<8>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        let txmsg = msg.clone();
+        let txfut = tokio::task::spawn(async move {
+            let prod = SRC.stream_producer();
+            prod.wait_write_all(&txmsg).await.unwrap();
+            prod.close();
+        });
+        let rxfut = tokio::task::spawn(async move {
+            let cons = DST.stream_consumer();
+            let mut buf = vec![0u8; 100];
+            cons.wait_read_exact_into(&mut buf).await.unwrap();
+            buf
+        });
+        wait_forward(&SRC.stream_consumer(), &DST.stream_producer())
+            .await
+            .unwrap();
+        txfut.await.unwrap();
+        assert_eq!(rxfut.await.unwrap(), msg);
+
+        // Framed to framed, through a tee with a small capture queue
+        static FSRC: BBQueue<Inline<32>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        static FDST: BBQueue<Inline<32>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        static CAPTURE: BBQueue<Inline<16>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        let txmsg = msg.clone();
+        let txfut = tokio::task::spawn(async move {
+            let prod: FramedProducer<_> = FSRC.framed_producer();
+            for chunk in txmsg.chunks(7) {
+                prod.wait_push_frame(chunk).await.unwrap();
+            }
+            prod.close();
+        });
+        let expected = msg.clone();
+        let rxfut = tokio::task::spawn(async move {
+            let cons: FramedConsumer<_> = FDST.framed_consumer();
+            let mut buf = [0u8; 16];
+            for chunk in expected.chunks(7) {
+                let n = cons.wait_pop_frame_into(&mut buf).await.unwrap();
+                assert_eq!(&buf[..n], chunk);
+            }
+        });
+        let mut tee = Tee::new(
+            FSRC.framed_consumer(),
+            FDST.framed_producer(),
+            CAPTURE.framed_producer(),
+        );
+        tee.run().await.unwrap();
+        txfut.await.unwrap();
+        rxfut.await.unwrap();
+
+        // The capture queue was never drained, so only the first and the last,
+        // shorter, frame fit
+        let capture: FramedConsumer<_> = CAPTURE.framed_consumer();
+        let mut buf = [0u8; 16];
+        assert_eq!(capture.pop_frame_into(&mut buf), Ok(7));
+        assert_eq!(&buf[..7], &msg[..7]);
+        assert_eq!(capture.pop_frame_into(&mut buf), Ok(2));
+        assert_eq!(&buf[..2], &msg[98..]);
+        assert!(capture.is_empty());
+        assert_eq!(tee.dropped(), 13);
+
+        // Frames are forwarded until the source is closed
+        let (src, dst, _) = tee.into_inner();
+        let cons: FramedConsumer<_> = FDST.framed_consumer();
+        assert_eq!(wait_forward_frames(&src, &dst).await, Ok(()));
+        assert!(cons.is_empty());
+    }
+}
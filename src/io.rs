@@ -0,0 +1,308 @@
+//! Pumps between queues and `embedded-io-async` devices
+//!
+//! These are the loops that move data between a queue and a device such as a
+//! UART, which would otherwise be written by hand for every project:
+//!
+//! * [`pump_to_writer`](crate::io::pump_to_writer) and
+//!   [`pump_frames_to_writer`](crate::io::pump_frames_to_writer) send the
+//!   contents of a queue to a [`Write`](embedded_io_async::Write) device.
+//! * [`pump_from_reader`](crate::io::pump_from_reader) and
+//!   [`pump_frames_from_reader`](crate::io::pump_frames_from_reader) fill a
+//!   queue with the data received from a [`Read`](embedded_io_async::Read) device.
+//!
+//! All of them run until the queue is closed, or the device fails.
+//!
+//! ## Cancellation
+//!
+//! Data is only released or committed once the device has accepted or provided
+//! it, so dropping a pump never loses data held in the queue. However, the
+//! device may already have sent part of a chunk when a pump is dropped while
+//! waiting for a write, in which case that part is sent again by the next pump.
+//! Likewise, data that the device provided to a dropped read is lost.
+
+use embedded_io_async::{Read, Write};
+
+use crate::{
+    prod_cons::{
+        framed::{FramedConsumer, FramedProducer, LenHeader},
+        stream::{StreamConsumer, StreamProducer},
+    },
+    traits::{
+        bbqhdl::BbqHandle,
+        coordination::{ReadGrantError, WriteGrantError},
+        notifier::AsyncNotifier,
+    },
+};
+
+/// Errors associated with pumping data between a queue and a device
+#[derive(PartialEq, Debug)]
+pub enum PumpError<E> {
+    /// The device returned an error
+    Io(E),
+    /// The device accepted zero bytes of a non-empty write
+    WriteZero,
+    /// Reading from the queue failed, other than by being closed
+    Read(ReadGrantError),
+    /// Writing to the queue failed, other than by being closed
+    Write(WriteGrantError),
+}
+
+/// Send all data from a stream queue to `writer`
+///
+/// Partial writes release only the data the writer accepted. When the data
+/// wraps around the end of the buffer, the two parts are written separately.
+/// Returns `Ok(())` once the queue has been closed and drained, after flushing
+/// the writer.
+pub async fn pump_to_writer<Q, W>(
+    cons: &StreamConsumer<Q>,
+    writer: &mut W,
+) -> Result<(), PumpError<W::Error>>
+where
+    Q: BbqHandle,
+    Q::Notifier: AsyncNotifier,
+    W: Write,
+{
+    loop {
+        let rgr = match cons.wait_read().await {
+            Ok(rgr) => rgr,
+            Err(ReadGrantError::Closed) => return writer.flush().await.map_err(PumpError::Io),
+            Err(e) => return Err(PumpError::Read(e)),
+        };
+        let used = writer.write(&rgr).await.map_err(PumpError::Io)?;
+        if used == 0 {
+            return Err(PumpError::WriteZero);
+        }
+        rgr.release(used);
+    }
+}
+
+/// Send all frames from a framed queue to `writer`
+///
+/// Each frame is written completely before it is released. Frame boundaries are
+/// not marked in any way, see [`codec`](crate::codec) for delimited formats.
+/// Returns `Ok(())` once the queue has been closed and drained, after flushing
+/// the writer.
+pub async fn pump_frames_to_writer<Q, H, W>(
+    cons: &FramedConsumer<Q, H>,
+    writer: &mut W,
+) -> Result<(), PumpError<W::Error>>
+where
+    Q: BbqHandle,
+    Q::Notifier: AsyncNotifier,
+    H: LenHeader,
+    W: Write,
+{
+    loop {
+        let rgr = match cons.wait_read().await {
+            Ok(rgr) => rgr,
+            Err(ReadGrantError::Closed) => return writer.flush().await.map_err(PumpError::Io),
+            Err(e) => return Err(PumpError::Read(e)),
+        };
+        let mut remain: &[u8] = &rgr;
+        while !remain.is_empty() {
+            let used = writer.write(remain).await.map_err(PumpError::Io)?;
+            if used == 0 {
+                return Err(PumpError::WriteZero);
+            }
+            remain = &remain[used..];
+        }
+        rgr.release();
+    }
+}
+
+/// Fill a stream queue with data from `reader`
+///
+/// Each read fills as much of the contiguous free space as the reader provides,
+/// so a read never spans the wrap around point. Returns `Ok(())` once the queue
+/// has been closed, or the reader reaches the end of its data.
+pub async fn pump_from_reader<Q, R>(
+    reader: &mut R,
+    prod: &StreamProducer<Q>,
+) -> Result<(), PumpError<R::Error>>
+where
+    Q: BbqHandle,
+    Q::Notifier: AsyncNotifier,
+    R: Read,
+{
+    loop {
+        let mut wgr = match prod.wait_grant_max_remaining(prod.capacity()).await {
+            Ok(wgr) => wgr,
+            Err(WriteGrantError::Closed) => return Ok(()),
+            Err(e) => return Err(PumpError::Write(e)),
+        };
+        let used = reader.read(&mut wgr).await.map_err(PumpError::Io)?;
+        if used == 0 {
+            return Ok(());
+        }
+        wgr.commit(used);
+    }
+}
+
+/// Fill a framed queue with data from `reader`, one frame per read
+///
+/// This suits devices that return a whole packet from each read, such as a UART
+/// that detects idle lines. Each read is given a grant of `max_frame` bytes, so
+/// that packets of up to that size are never split. Returns `Ok(())` once the
+/// queue has been closed, or the reader reaches the end of its data.
+pub async fn pump_frames_from_reader<Q, H, R>(
+    reader: &mut R,
+    prod: &FramedProducer<Q, H>,
+    max_frame: H,
+) -> Result<(), PumpError<R::Error>>
+where
+    Q: BbqHandle,
+    Q::Notifier: AsyncNotifier,
+    H: LenHeader,
+    R: Read,
+{
+    loop {
+        let mut wgr = match prod.wait_grant(max_frame).await {
+            Ok(wgr) => wgr,
+            Err(WriteGrantError::Closed) => return Ok(()),
+            Err(e) => return Err(PumpError::Write(e)),
+        };
+        let used = reader.read(&mut wgr).await.map_err(PumpError::Io)?;
+        if used == 0 {
+            return Ok(());
+        }
+        wgr.commit_len(used);
+    }
+}
+
+#[cfg(all(test, target_has_atomic = "ptr"))]
+mod test {
+    use crate::{
+        queue::BBQueue,
+        traits::{coordination::cas::AtomicCoord, notifier::maitake::MaiNotSpsc, storage::Inline},
+    };
+
+    #[cfg(feature = "embedded-io-async-0_6")]
+    #[tokio::test]
+    async fn io_pumps() {
+        use crate::io::{
+            PumpError, pump_frames_from_reader, pump_frames_to_writer, pump_from_reader,
+            pump_to_writer,
+        };
+        use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+
+        /// Accepts at most `chunk` bytes per write
+        struct MockWriter {
+            out: Vec<u8>,
+            chunk: usize,
+        }
+        impl ErrorType for MockWriter {
+            type Error = ErrorKind;
+        }
+        impl Write for MockWriter {
+            async fn write(&mut self, buf: &[u8]) -> Result<usize, ErrorKind> {
+                let n = buf.len().min(self.chunk);
+                self.out.extend_from_slice(&buf[..n]);
+                Ok(n)
+            }
+        }
+
+        /// Provides one packet per read, then the end of the data
+        struct MockReader {
+            packets: Vec<Vec<u8>>,
+        }
+        impl ErrorType for MockReader {
+            type Error = ErrorKind;
+        }
+        impl Read for MockReader {
+            async fn read(&mut self, buf: &mut [u8]) -> Result<usize, ErrorKind> {
+                if self.packets.is_empty() {
+                    return Ok(0);
+                }
+                let pkt = &mut self.packets[0];
+                let n = buf.len().min(pkt.len());
+                buf[..n].copy_from_slice(&pkt[..n]);
+                pkt.drain(..n);
+                if pkt.is_empty() {
+                    self.packets.remove(0);
+                }
+                Ok(n)
+            }
+        }
+
+        let msg: Vec<u8> = (0..100).collect();
+        let packets = || msg.chunks(7).map(|c| c.to_vec()).collect::<Vec<_>>();
+
+        // Stream queue to a writer, with partial writes and wrap-arounds
+        static STREAM_TX: BBQueue<Inline<16>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        let prod = STREAM_TX.stream_producer();
+        let sent = msg.clone();
+        let txfut = tokio::task::spawn(async move {
+            prod.wait_write_all(&sent).await.unwrap();
+            prod.close();
+        });
+        let mut writer = MockWriter {
+            out: Vec::new(),
+            chunk: 5,
+        };
+        pump_to_writer(&STREAM_TX.stream_consumer(), &mut writer)
+            .await
+            .unwrap();
+        txfut.await.unwrap();
+        assert_eq!(writer.out, msg);
+
+        // Reader to a stream queue, until the end of the data
+        static STREAM_RX: BBQueue<Inline<16>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        let mut reader = MockReader { packets: packets() };
+        let rxfut = tokio::task::spawn(async move {
+            let mut buf = [0u8; 100];
+            let cons = STREAM_RX.stream_consumer();
+            cons.wait_read_exact_into(&mut buf).await.unwrap();
+            buf
+        });
+        pump_from_reader(&mut reader, &STREAM_RX.stream_producer())
+            .await
+            .unwrap();
+        assert_eq!(rxfut.await.unwrap().as_slice(), msg.as_slice());
+
+        // Framed queue to a writer
+        static FRAMED_TX: BBQueue<Inline<32>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        let prod = FRAMED_TX.framed_producer();
+        let sent = packets();
+        let txfut = tokio::task::spawn(async move {
+            for pkt in sent {
+                prod.wait_push_frame(&pkt).await.unwrap();
+            }
+            prod.close();
+        });
+        let mut writer = MockWriter {
+            out: Vec::new(),
+            chunk: 3,
+        };
+        pump_frames_to_writer(&FRAMED_TX.framed_consumer(), &mut writer)
+            .await
+            .unwrap();
+        txfut.await.unwrap();
+        assert_eq!(writer.out, msg);
+
+        // Reader to a framed queue, one frame per packet
+        static FRAMED_RX: BBQueue<Inline<32>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        let mut reader = MockReader { packets: packets() };
+        let expected = packets();
+        let rxfut = tokio::task::spawn(async move {
+            let cons = FRAMED_RX.framed_consumer();
+            let mut buf = [0u8; 16];
+            for pkt in expected {
+                let n = cons.wait_pop_frame_into(&mut buf).await.unwrap();
+                assert_eq!(&buf[..n], pkt.as_slice());
+            }
+        });
+        let prod = FRAMED_RX.framed_producer();
+        pump_frames_from_reader(&mut reader, &prod, 8)
+            .await
+            .unwrap();
+        rxfut.await.unwrap();
+
+        // A closed queue stops the pump
+        prod.close();
+        let mut reader = MockReader { packets: packets() };
+        let res: Result<(), PumpError<ErrorKind>> =
+            pump_frames_from_reader(&mut reader, &prod, 8).await;
+        assert_eq!(res, Ok(()));
+        assert_eq!(reader.packets.len(), packets().len());
+    }
+}
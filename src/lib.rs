@@ -4,10 +4,28 @@
 
 #![cfg_attr(not(any(test, feature = "std")), no_std)]
 
+/// Delimited codecs between framed and stream queues
+///
+pub mod codec;
+
+/// Forwarding between queues
+///
+pub mod forward;
+
 /// Type aliases for different generic configurations
 ///
 pub mod nicknames;
 
+/// Pumps between queues and `embedded-io-async` devices
+///
+#[cfg(feature = "embedded-io-async-0_6")]
+pub mod io;
+
+/// Logging backends
+///
+#[cfg(any(feature = "log-0_4", feature = "defmt-1"))]
+pub mod logger;
+
 /// Producer and consumer interfaces
 ///
 pub mod prod_cons;
@@ -16,6 +34,10 @@ pub mod prod_cons;
 ///
 pub mod queue;
 
+/// Waiting on several queues at once
+///
+pub mod select;
+
 /// Generic traits
 ///
 pub mod traits;
@@ -147,7 +169,7 @@ mod test {
         let cons = BBQ.stream_consumer();
 
         let rxfut = tokio::task::spawn(async move {
-            let rgr = cons.wait_read().await;
+            let rgr = cons.wait_read().await.unwrap();
             assert_eq!(rgr.deref(), &[1, 2, 3]);
         });
 
@@ -170,7 +192,7 @@ mod test {
         let cons = BBQ.framed_consumer();
 
         let rxfut = tokio::task::spawn(async move {
-            let rgr = cons.wait_read().await;
+            let rgr = cons.wait_read().await.unwrap();
             assert_eq!(rgr.deref(), &[1, 2, 3]);
         });
 
@@ -194,7 +216,7 @@ mod test {
         let cons = bbq.stream_consumer();
 
         let rxfut = tokio::task::spawn(async move {
-            let rgr = cons.wait_read().await;
+            let rgr = cons.wait_read().await.unwrap();
             assert_eq!(rgr.deref(), &[1, 2, 3]);
         });
 
@@ -218,7 +240,7 @@ mod test {
         let cons = bbq.stream_consumer();
 
         let rxfut = tokio::task::spawn(async move {
-            let rgr = cons.wait_read().await;
+            let rgr = cons.wait_read().await.unwrap();
             assert_eq!(rgr.deref(), &[1, 2, 3]);
         });
 
@@ -0,0 +1,441 @@
+//! Logging backends
+//!
+//! These write log records into a queue, one frame per record, so that they
+//! can be sent out later, e.g. from an idle loop or a DMA transfer, instead of
+//! blocking the code doing the logging.
+//!
+//! * [`Logger`](crate::logger::Logger) implements `log::Log`, with the
+//!   `log-0_4` feature. Each frame holds one formatted line of text.
+//! * [`DefmtLogger`](crate::logger::DefmtLogger) backs a `defmt` global logger,
+//!   with the `defmt-1` feature. Each frame holds one encoded defmt frame. Use
+//!   [`defmt_global_logger!`](crate::defmt_global_logger) to register it.
+//!
+//! Both only need a producer for the queue, which they create themselves, so
+//! they can live in statics next to a borrowed queue like
+//! [`Jerk`](crate::nicknames::Jerk) or [`Churrasco`](crate::nicknames::Churrasco).
+//! Records that don't fit in the queue are dropped and counted, and never block.
+//! This includes records logged from an interrupt that preempted another record
+//! being written to the same queue.
+//!
+//! Use [`drain`](crate::logger::drain) on the consumer side to forward the
+//! logged frames to any byte sink.
+
+use core::{
+    marker::PhantomData,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    prod_cons::framed::{FramedConsumer, FramedProducer, LenHeader},
+    traits::{bbqhdl::BbqHandle, mode::AllowsHeader},
+};
+
+#[cfg(feature = "defmt-1")]
+use crate::prod_cons::framed::FramedGrantW;
+#[cfg(feature = "defmt-1")]
+use core::cell::UnsafeCell;
+
+/// Forward all frames currently in the queue to `sink`
+///
+/// Each frame is released once `sink` accepts it. If `sink` returns an error,
+/// that frame is kept in the queue, and the error is returned. Otherwise, the
+/// number of frames forwarded is returned.
+pub fn drain<Q, H, E>(
+    cons: &FramedConsumer<Q, H>,
+    mut sink: impl FnMut(&[u8]) -> Result<(), E>,
+) -> Result<usize, E>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    let mut frames = 0;
+    loop {
+        // Stop when nothing is left to forward, or the queue is being read elsewhere
+        let Ok(rgr) = cons.read() else {
+            return Ok(frames);
+        };
+        if let Err(e) = sink(&rgr) {
+            rgr.keep();
+            return Err(e);
+        }
+        rgr.release();
+        frames += 1;
+    }
+}
+
+fn bump(ctr: &AtomicUsize) {
+    // Only load/store, see `StatsCoord`
+    let old = ctr.load(Ordering::Relaxed);
+    ctr.store(old.wrapping_add(1), Ordering::Relaxed);
+}
+
+// ---- log ----
+
+/// A `log::Log` implementation that writes each record as a line of text
+///
+/// Records are formatted directly into the queue with
+/// [`FramedProducer::write_fmt`], as `"LEVEL [target] message\n"`. Set it up
+/// with `log::set_logger`:
+///
+/// ```rust
+/// # #[cfg(target_has_atomic = "ptr")]
+/// # {
+/// use bbq2::{logger::Logger, nicknames::Churrasco};
+///
+/// static QUEUE: Churrasco<256> = Churrasco::new();
+/// static LOGGER: Logger<&Churrasco<256>> = Logger::new(&QUEUE);
+///
+/// log::set_logger(&LOGGER).unwrap();
+/// log::set_max_level(log::LevelFilter::Info);
+/// # }
+/// ```
+#[cfg(feature = "log-0_4")]
+pub struct Logger<Q, H = u16>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    prod: FramedProducer<Q, H>,
+    dropped: AtomicUsize,
+}
+
+#[cfg(feature = "log-0_4")]
+impl<Q, H> Logger<Q, H>
+where
+    Q: BbqHandle<Target = Q>,
+    Q::Mode: AllowsHeader<H>,
+    H: LenHeader,
+{
+    /// Create a logger writing to the given queue
+    pub const fn new(bbq: Q) -> Self {
+        Self {
+            prod: FramedProducer {
+                bbq,
+                pd: PhantomData,
+                taken: false,
+            },
+            dropped: AtomicUsize::new(0),
+        }
+    }
+}
+
+#[cfg(feature = "log-0_4")]
+impl<Q, H> Logger<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    /// The number of records dropped because they did not fit in the queue
+    ///
+    /// Records logged concurrently from multiple contexts may not all be counted.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(feature = "log-0_4")]
+impl<Q, H> log::Log for Logger<Q, H>
+where
+    Q: BbqHandle,
+    Q::Target: Send + Sync,
+    H: LenHeader + Send + Sync,
+{
+    fn enabled(&self, _metadata: &log::Metadata<'_>) -> bool {
+        // Filtering is done with `log::set_max_level`
+        true
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        let res = self.prod.write_fmt(format_args!(
+            "{} [{}] {}\n",
+            record.level(),
+            record.target(),
+            record.args()
+        ));
+        if res.is_err() {
+            bump(&self.dropped);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+// ---- defmt ----
+
+/// The state behind a `defmt` global logger
+///
+/// Each defmt frame is encoded directly into a grant of the queue. A critical
+/// section is held from `acquire` to `release`, as defmt requires, so the
+/// queue should use [`CsCoord`](crate::traits::coordination::cs::CsCoord).
+///
+/// This can't be used as the global logger by itself, use
+/// [`defmt_global_logger!`](crate::defmt_global_logger) instead.
+#[cfg(feature = "defmt-1")]
+pub struct DefmtLogger<Q, H = u16>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    prod: FramedProducer<Q, H>,
+    dropped: AtomicUsize,
+    state: UnsafeCell<DefmtState<Q, H>>,
+}
+
+#[cfg(feature = "defmt-1")]
+struct DefmtState<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    taken: bool,
+    /// Frames started while another frame was being written, e.g. by a panic
+    nested: usize,
+    restore: critical_section::RestoreState,
+    encoder: defmt::Encoder,
+    frame: DefmtFrame<Q, H>,
+}
+
+/// The grant holding the frame currently being logged
+#[cfg(feature = "defmt-1")]
+struct DefmtFrame<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    grant: Option<FramedGrantW<Q, H>>,
+    used: usize,
+    overrun: bool,
+}
+
+#[cfg(feature = "defmt-1")]
+impl<Q, H> DefmtFrame<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    fn push(&mut self, data: &[u8]) {
+        let Some(grant) = self.grant.as_mut() else {
+            return;
+        };
+        match grant.get_mut(self.used..self.used + data.len()) {
+            Some(dst) => {
+                dst.copy_from_slice(data);
+                self.used += data.len();
+            }
+            None => self.overrun = true,
+        }
+    }
+}
+
+#[cfg(feature = "defmt-1")]
+impl<Q, H> DefmtLogger<Q, H>
+where
+    Q: BbqHandle<Target = Q>,
+    Q::Mode: AllowsHeader<H>,
+    H: LenHeader,
+{
+    /// Create a logger writing to the given queue
+    pub const fn new(bbq: Q) -> Self {
+        Self {
+            prod: FramedProducer {
+                bbq,
+                pd: PhantomData,
+                taken: false,
+            },
+            dropped: AtomicUsize::new(0),
+            state: UnsafeCell::new(DefmtState {
+                taken: false,
+                nested: 0,
+                restore: critical_section::RestoreState::invalid(),
+                encoder: defmt::Encoder::new(),
+                frame: DefmtFrame {
+                    grant: None,
+                    used: 0,
+                    overrun: false,
+                },
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "defmt-1")]
+impl<Q, H> DefmtLogger<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    /// The number of frames dropped because they did not fit in the queue, or
+    /// were logged while another frame was being written
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Start a frame, see `defmt::Logger::acquire`
+    ///
+    /// A frame started while another one is being written, e.g. by a panic in
+    /// the middle of a log statement, is dropped. Panicking instead would
+    /// recurse when defmt is also used to log panics.
+    pub fn acquire(&self) {
+        // SAFETY: The critical section is released in `release`, or below, in
+        // the reverse order of acquiring it
+        let restore = unsafe { critical_section::acquire() };
+        // SAFETY: We hold a critical section, so only this context, or code
+        // called from it, can access the state
+        let st = unsafe { &mut *self.state.get() };
+        if st.taken {
+            // The outer frame still holds its own critical section
+            st.nested += 1;
+            // SAFETY: This is the innermost critical section, acquired above
+            unsafe { critical_section::release(restore) }
+            return;
+        }
+        st.taken = true;
+        st.restore = restore;
+
+        st.frame = DefmtFrame {
+            grant: self.prod.grant_max_remaining(H::MAX).ok(),
+            used: 0,
+            overrun: false,
+        };
+        st.encoder.start_frame(|b| st.frame.push(b));
+    }
+
+    /// Write to the current frame, see `defmt::Logger::write`
+    ///
+    /// # Safety
+    ///
+    /// Must only be called between [`DefmtLogger::acquire`] and
+    /// [`DefmtLogger::release`].
+    pub unsafe fn write(&self, bytes: &[u8]) {
+        // SAFETY: The caller holds the state, see `acquire`
+        let st = unsafe { &mut *self.state.get() };
+        if st.nested != 0 {
+            return;
+        }
+        st.encoder.write(bytes, |b| st.frame.push(b));
+    }
+
+    /// Finish the current frame, see `defmt::Logger::release`
+    ///
+    /// The frame is committed to the queue, or dropped if it did not fit.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called once after [`DefmtLogger::acquire`].
+    pub unsafe fn release(&self) {
+        // SAFETY: The caller holds the state, see `acquire`
+        let st = unsafe { &mut *self.state.get() };
+        if st.nested != 0 {
+            st.nested -= 1;
+            bump(&self.dropped);
+            return;
+        }
+        st.encoder.end_frame(|b| st.frame.push(b));
+
+        match st.frame.grant.take() {
+            Some(grant) if !st.frame.overrun => {
+                grant.commit_len(st.frame.used);
+            }
+            _ => bump(&self.dropped),
+        }
+
+        st.taken = false;
+        let restore = st.restore;
+        // SAFETY: This releases the critical section acquired in `acquire`
+        unsafe { critical_section::release(restore) }
+    }
+}
+
+// SAFETY: The state is only accessed while holding a critical section
+#[cfg(feature = "defmt-1")]
+unsafe impl<Q, H> Sync for DefmtLogger<Q, H>
+where
+    Q: BbqHandle + Send + Sync,
+    H: LenHeader + Send,
+{
+}
+
+/// Register a [`DefmtLogger`] writing to a static queue as the `defmt` global logger
+///
+/// This defines a static `$logger`, which can be used to check the number of
+/// dropped frames. Read the frames from the queue with a framed consumer, e.g.
+/// using [`drain`].
+///
+/// ```rust,ignore
+/// use bbq2::nicknames::Jerk;
+///
+/// static QUEUE: Jerk<1024> = Jerk::new();
+/// bbq2::defmt_global_logger!(LOGGER = QUEUE: Jerk<1024>);
+/// ```
+#[cfg(feature = "defmt-1")]
+#[macro_export]
+macro_rules! defmt_global_logger {
+    ($logger:ident = $queue:path: $ty:ty) => {
+        static $logger: $crate::logger::DefmtLogger<&'static $ty> =
+            $crate::logger::DefmtLogger::new(&$queue);
+
+        const _: () = {
+            #[defmt::global_logger]
+            struct BbqGlobalLogger;
+
+            unsafe impl defmt::Logger for BbqGlobalLogger {
+                fn acquire() {
+                    $logger.acquire();
+                }
+
+                unsafe fn flush() {}
+
+                unsafe fn release() {
+                    unsafe { $logger.release() }
+                }
+
+                unsafe fn write(bytes: &[u8]) {
+                    unsafe { $logger.write(bytes) }
+                }
+            }
+        };
+    };
+}
+
+#[cfg(all(test, target_has_atomic = "ptr", feature = "log-0_4"))]
+mod test {
+    #[test]
+    fn logger() {
+        use crate::{
+            logger::{Logger, drain},
+            nicknames::Churrasco,
+            prod_cons::framed::FramedConsumer,
+        };
+        use log::Log;
+
+        static QUEUE: Churrasco<64> = Churrasco::new();
+        static LOGGER: Logger<&Churrasco<64>> = Logger::new(&QUEUE);
+        let cons: FramedConsumer<_, u16> = QUEUE.framed_consumer();
+
+        let log = |msg: &str| {
+            LOGGER.log(
+                &log::Record::builder()
+                    .args(format_args!("{msg}"))
+                    .level(log::Level::Warn)
+                    .target("app")
+                    .build(),
+            )
+        };
+        log("hello");
+        log(&"x".repeat(64));
+        assert_eq!(LOGGER.dropped(), 1);
+
+        let mut out = Vec::new();
+        let res: Result<usize, ()> = drain(&cons, |frame| {
+            out.extend_from_slice(frame);
+            Ok(())
+        });
+        assert_eq!(res, Ok(1));
+        assert_eq!(out, b"WARN [app] hello\n");
+
+        // Frames rejected by the sink stay in the queue
+        log("again");
+        assert_eq!(drain(&cons, |_| Err(())), Err(()));
+        assert_eq!(drain(&cons, |_| Ok::<_, ()>(())), Ok(1));
+    }
+}
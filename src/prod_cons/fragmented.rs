@@ -0,0 +1,365 @@
+//! Fragmented message interfaces
+//!
+//! Useful for sending messages that may be larger than the queue itself, e.g.
+//! firmware update images. Each message is split into one or more chunks, which
+//! are sent as frames using a [`FramedProducer`]. The first byte of each frame
+//! holds flags marking the first and last chunk of a message.
+//!
+//! Chunks are sent as space becomes available, so the consumer must read them
+//! concurrently for a large message to make progress.
+
+use core::ops::Deref;
+
+use crate::traits::{
+    bbqhdl::BbqHandle,
+    coordination::{ReadGrantError, WriteGrantError},
+    notifier::AsyncNotifier,
+};
+
+use super::framed::{FramedConsumer, FramedGrantR, FramedProducer, LenHeader};
+
+/// Flag set on the first chunk of a message
+const FIRST: u8 = 0x01;
+/// Flag set on the last chunk of a message
+const LAST: u8 = 0x02;
+
+/// A producer handle that splits messages into chunks
+pub struct FragmentedProducer<Q, H = u16>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    inner: FramedProducer<Q, H>,
+}
+
+/// A consumer handle that reads the chunks of messages
+pub struct FragmentedConsumer<Q, H = u16>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    inner: FramedConsumer<Q, H>,
+}
+
+/// A single chunk of a message
+///
+/// Chunks implement Deref to access the data of the chunk, without the flags.
+#[must_use = "Read Grants must be released to free space"]
+pub struct ChunkGrantR<Q, H = u16>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    inner: FramedGrantR<Q, H>,
+}
+
+/// Errors associated with reassembling a message
+#[derive(PartialEq, Debug)]
+pub enum ReassembleError {
+    /// Reading a chunk failed
+    Read(ReadGrantError),
+    /// The message did not fit in the provided buffer. The rest of the message
+    /// has been discarded.
+    BufferTooSmall,
+    /// A chunk was received that did not start a message, e.g. because the
+    /// consumer started reading in the middle of a message. It has been discarded.
+    MissingFirst,
+}
+
+// ---- impls ----
+
+// ---- FragmentedProducer ----
+
+impl<Q, H> FragmentedProducer<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    /// Create a fragmenting producer from a framed producer
+    pub fn new(inner: FramedProducer<Q, H>) -> Self {
+        Self { inner }
+    }
+
+    /// Obtain the inner framed producer
+    pub fn into_inner(self) -> FramedProducer<Q, H> {
+        self.inner
+    }
+
+    /// Write the next chunk of a message
+    ///
+    /// Writes as much of `data` as currently fits into a single chunk, and
+    /// returns the number of bytes written. Set `first` when `data` is the start
+    /// of a message. The chunk is marked as the last one of the message if all
+    /// of `data` was written.
+    ///
+    /// Fails with [`WriteGrantError::InsufficientSize`] if not even a single byte
+    /// of `data` fits right now, and with [`WriteGrantError::TooLarge`] if the
+    /// queue is too small to ever hold a chunk with data.
+    pub fn write_chunk(&self, data: &[u8], first: bool) -> Result<usize, WriteGrantError> {
+        if self.inner.max_frame_len() < 2 && !data.is_empty() {
+            return Err(WriteGrantError::TooLarge);
+        }
+        let max = H::try_from(data.len().saturating_add(1)).unwrap_or(H::MAX);
+        let mut wgr = self.inner.grant_max_remaining(max)?;
+        if wgr.len() < 2 && !data.is_empty() {
+            // Only room for the flags, wait for more space instead
+            wgr.abort();
+            return Err(WriteGrantError::InsufficientSize);
+        }
+
+        let used = data.len().min(wgr.len() - 1);
+        let mut flags = if first { FIRST } else { 0 };
+        if used == data.len() {
+            flags |= LAST;
+        }
+        wgr[0] = flags;
+        wgr[1..][..used].copy_from_slice(&data[..used]);
+
+        // `used + 1` is at most the length of the grant, so this always fits
+        let len = H::try_from(used + 1).unwrap_or(H::MAX);
+        wgr.commit(len);
+        Ok(used)
+    }
+}
+
+impl<Q, H> FragmentedProducer<Q, H>
+where
+    Q: BbqHandle,
+    Q::Notifier: AsyncNotifier,
+    H: LenHeader,
+{
+    /// Send a whole message, waiting for space to become available for each chunk
+    ///
+    /// Returns an error if the queue is closed while sending, or if it is too
+    /// small to ever hold a chunk, see [`FragmentedProducer::write_chunk`]. If this future is
+    /// dropped before completion, the consumer discards the partial message when
+    /// the next message starts.
+    pub async fn send(&self, msg: &[u8]) -> Result<(), WriteGrantError> {
+        let mut first = true;
+        let mut remain = msg;
+        loop {
+            let used = self
+                .inner
+                .bbq
+                .not
+                .wait_for_not_full(|| match self.write_chunk(remain, first) {
+                    Err(WriteGrantError::InsufficientSize | WriteGrantError::GrantInProgress) => {
+                        None
+                    }
+                    res => Some(res),
+                })
+                .await?;
+            first = false;
+            remain = &remain[used..];
+            if remain.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// ---- FragmentedConsumer ----
+
+impl<Q, H> FragmentedConsumer<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    /// Create a reassembling consumer from a framed consumer
+    pub fn new(inner: FramedConsumer<Q, H>) -> Self {
+        Self { inner }
+    }
+
+    /// Obtain the inner framed consumer
+    pub fn into_inner(self) -> FramedConsumer<Q, H> {
+        self.inner
+    }
+
+    /// Obtain the next chunk
+    ///
+    /// Frames that are too short to hold the flags of a chunk are released, and
+    /// reported as [`ReadGrantError::InconsistentFrameHeader`].
+    pub fn read_chunk(&self) -> Result<ChunkGrantR<Q, H>, ReadGrantError> {
+        let inner = self.inner.read()?;
+        if inner.is_empty() {
+            inner.release();
+            return Err(ReadGrantError::InconsistentFrameHeader);
+        }
+        Ok(ChunkGrantR { inner })
+    }
+}
+
+impl<Q, H> FragmentedConsumer<Q, H>
+where
+    Q: BbqHandle,
+    Q::Notifier: AsyncNotifier,
+    H: LenHeader,
+{
+    /// Wait for the next chunk to become available
+    pub async fn wait_read_chunk(&self) -> Result<ChunkGrantR<Q, H>, ReadGrantError> {
+        self.inner
+            .bbq
+            .not
+            .wait_for_not_empty(|| match self.read_chunk() {
+                Err(ReadGrantError::Empty | ReadGrantError::GrantInProgress) => None,
+                res => Some(res),
+            })
+            .await
+    }
+
+    /// Wait for a whole message, and copy it into `buf`
+    ///
+    /// Returns the length of the message. If a new message starts before the
+    /// current one is complete, the partial message is discarded.
+    pub async fn wait_reassemble(&self, buf: &mut [u8]) -> Result<usize, ReassembleError> {
+        // `None` until the first chunk of a message has been seen
+        let mut len = None;
+        let mut too_small = false;
+        loop {
+            let chunk = self
+                .wait_read_chunk()
+                .await
+                .map_err(ReassembleError::Read)?;
+
+            if chunk.is_first() {
+                len = Some(0);
+                too_small = false;
+            }
+            let Some(cur) = len else {
+                chunk.release();
+                return Err(ReassembleError::MissingFirst);
+            };
+
+            let new_len = cur + chunk.len();
+            match buf.get_mut(cur..new_len) {
+                Some(dst) if !too_small => dst.copy_from_slice(&chunk),
+                _ => too_small = true,
+            }
+            len = Some(new_len);
+
+            let last = chunk.is_last();
+            chunk.release();
+            if last {
+                return if too_small {
+                    Err(ReassembleError::BufferTooSmall)
+                } else {
+                    Ok(new_len)
+                };
+            }
+        }
+    }
+}
+
+// ---- ChunkGrantR ----
+
+impl<Q, H> ChunkGrantR<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    /// Is this the first chunk of a message?
+    pub fn is_first(&self) -> bool {
+        self.inner[0] & FIRST != 0
+    }
+
+    /// Is this the last chunk of a message?
+    pub fn is_last(&self) -> bool {
+        self.inner[0] & LAST != 0
+    }
+
+    /// Release the chunk, making the space available to the producer
+    pub fn release(self) {
+        self.inner.release();
+    }
+
+    /// Drop the chunk WITHOUT releasing it from the queue.
+    ///
+    /// The next call to read will observe the same chunk again.
+    pub fn keep(self) {
+        self.inner.keep();
+    }
+}
+
+impl<Q, H> Deref for ChunkGrantR<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner[1..]
+    }
+}
+
+#[cfg(all(test, target_has_atomic = "ptr"))]
+mod test {
+    use core::{ops::Deref, time::Duration};
+
+    use crate::{
+        queue::BBQueue,
+        traits::{coordination::cas::AtomicCoord, notifier::maitake::MaiNotSpsc, storage::Inline},
+    };
+
+    #[tokio::test]
+    async fn fragmented() {
+        use crate::{
+            prod_cons::fragmented::{FragmentedConsumer, FragmentedProducer, ReassembleError},
+            traits::coordination::WriteGrantError,
+        };
+
+        static BBQ: BBQueue<Inline<16>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        let prod = FragmentedProducer::new(BBQ.framed_producer());
+        let cons = FragmentedConsumer::new(BBQ.framed_consumer());
+
+        // Chunks carry first/last markers
+        assert_eq!(prod.write_chunk(&[1, 2, 3], true), Ok(3));
+        let chunk = cons.read_chunk().unwrap();
+        assert!(chunk.is_first() && chunk.is_last());
+        assert_eq!(chunk.deref(), &[1, 2, 3]);
+        chunk.release();
+
+        // A chunk from the middle of a message is rejected
+        assert_eq!(prod.write_chunk(&[4], false), Ok(1));
+        let mut buf = [0u8; 100];
+        assert_eq!(
+            cons.wait_reassemble(&mut buf).await,
+            Err(ReassembleError::MissingFirst)
+        );
+
+        // Messages much larger than the queue are split up
+        let msg: [u8; 100] = core::array::from_fn(|i| i as u8);
+        let txfut = tokio::task::spawn(async move {
+            prod.send(&msg).await.unwrap();
+            prod.send(&msg).await.unwrap();
+        });
+        let rxfut = tokio::task::spawn(async move {
+            let mut buf = [0u8; 100];
+            assert_eq!(cons.wait_reassemble(&mut buf).await, Ok(100));
+            assert_eq!(buf, msg);
+            let mut small = [0u8; 50];
+            assert_eq!(
+                cons.wait_reassemble(&mut small).await,
+                Err(ReassembleError::BufferTooSmall)
+            );
+            assert!(cons.into_inner().is_empty());
+        });
+
+        tokio::time::timeout(Duration::from_secs(1), rxfut)
+            .await
+            .unwrap()
+            .unwrap();
+        txfut.await.unwrap();
+
+        // Only room for the header and the flags, so data never fits
+        static TINY: BBQueue<Inline<3>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        let prod = FragmentedProducer::new(TINY.framed_producer());
+        assert_eq!(prod.write_chunk(&[1], true), Err(WriteGrantError::TooLarge));
+        assert_eq!(
+            tokio::time::timeout(Duration::from_secs(1), prod.send(&[1, 2]))
+                .await
+                .unwrap(),
+            Err(WriteGrantError::TooLarge)
+        );
+    }
+}
@@ -3,6 +3,7 @@
 //! Useful for sending data that has coherent frames, e.g. network packets
 
 use core::{
+    fmt,
     marker::PhantomData,
     ops::{Deref, DerefMut},
     ptr::NonNull,
@@ -10,7 +11,7 @@ use core::{
 
 use crate::traits::{
     bbqhdl::BbqHandle,
-    coordination::{Coord, ReadGrantError, WriteGrantError},
+    coordination::{Coord, ReadGrantError, Side, WriteGrantError},
     notifier::{AsyncNotifier, Notifier},
     storage::Storage,
 };
@@ -22,14 +23,18 @@ use crate::traits::{
 /// 64KiB at a time. You can also use a `usize` allowing the maximum platform
 /// available size.
 ///
-/// You should not have to implement this trait.
+/// This trait is sealed, and is only implemented for `u16` and `usize`.
 ///
 /// # Safety
 ///
 /// Do it right
-pub unsafe trait LenHeader: Into<usize> + Copy + Ord {
+pub unsafe trait LenHeader:
+    sealed::Sealed + Into<usize> + TryFrom<usize> + Copy + Ord
+{
     /// Should be `[u8; size_of::<Self>()]`
     type Bytes;
+    /// The largest length that can be represented by this header
+    const MAX: Self;
     /// Convert Self into Self::Bytes, in little endian order
     fn to_le_bytes(&self) -> Self::Bytes;
     /// Convert Self::Bytes (which is in little endian order) to Self.
@@ -44,6 +49,8 @@ where
 {
     pub(crate) bbq: Q::Target,
     pub(crate) pd: PhantomData<H>,
+    /// Was this handle taken with [`Coord::take`], and must be given back?
+    pub(crate) taken: bool,
 }
 
 /// A consumer handle that can be used to read framed chunks
@@ -54,6 +61,8 @@ where
 {
     pub(crate) bbq: Q::Target,
     pub(crate) pd: PhantomData<H>,
+    /// Was this handle taken with [`Coord::take`], and must be given back?
+    pub(crate) taken: bool,
 }
 
 /// A writing grant into the storage buffer
@@ -68,6 +77,8 @@ where
     bbq: Q::Target,
     base_ptr: NonNull<u8>,
     hdr: H,
+    /// Write cursor used by the [`fmt::Write`] impl
+    written: usize,
 }
 
 /// A reading grant into the storage buffer
@@ -91,8 +102,16 @@ where
 
 // ---- impl LenHeader ----
 
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for u16 {}
+    impl Sealed for usize {}
+}
+
 unsafe impl LenHeader for u16 {
     type Bytes = [u8; 2];
+    const MAX: Self = u16::MAX;
 
     #[inline(always)]
     fn to_le_bytes(&self) -> Self::Bytes {
@@ -107,6 +126,7 @@ unsafe impl LenHeader for u16 {
 
 unsafe impl LenHeader for usize {
     type Bytes = [u8; core::mem::size_of::<usize>()];
+    const MAX: Self = usize::MAX;
 
     #[inline(always)]
     fn to_le_bytes(&self) -> Self::Bytes {
@@ -131,9 +151,18 @@ where
     /// The returned grant can be used to write up to `sz` bytes, though
     /// a smaller size may be committed. Dropping the grant without calling
     /// commit means that no data will be made visible to the consumer.
+    ///
+    /// Returns [`WriteGrantError::TooLarge`] if the frame and its header exceed
+    /// the capacity of the buffer. See [`StreamProducer::grant_exact`] for the
+    /// other errors.
+    ///
+    /// [`StreamProducer::grant_exact`]: crate::prod_cons::stream::StreamProducer::grant_exact
     pub fn grant(&self, sz: H) -> Result<FramedGrantW<Q, H>, WriteGrantError> {
         let (ptr, cap) = self.bbq.sto.ptr_len();
-        let needed = sz.into() + core::mem::size_of::<H>();
+        let needed = sz
+            .into()
+            .checked_add(core::mem::size_of::<H>())
+            .ok_or(WriteGrantError::TooLarge)?;
 
         let offset = self.bbq.cor.grant_exact(cap, needed)?;
 
@@ -145,8 +174,117 @@ where
             bbq: self.bbq.clone(),
             base_ptr,
             hdr: sz,
+            written: 0,
         })
     }
+
+    /// Attempt to obtain the largest write grant available, up to `max` bytes
+    ///
+    /// This is useful when the length of a frame is not known before encoding it.
+    /// Unlike [`StreamProducer::grant_max_remaining`], this will wrap around to the
+    /// start of the buffer early if more space is available there. Only the
+    /// length given to [`FramedGrantW::commit`] is kept, the rest of the
+    /// reservation is returned to the queue.
+    ///
+    /// If we return a grant, it will have a nonzero amount of space.
+    ///
+    /// [`StreamProducer::grant_max_remaining`]: crate::prod_cons::stream::StreamProducer::grant_max_remaining
+    pub fn grant_max_remaining(&self, max: H) -> Result<FramedGrantW<Q, H>, WriteGrantError> {
+        let (ptr, cap) = self.bbq.sto.ptr_len();
+        let hdr_sz = const { core::mem::size_of::<H>() };
+
+        // See `Coord::snapshot` for when this stays valid
+        let avail = self.bbq.cor.snapshot().max_grant_len(cap);
+        let needed = avail.min(max.into().saturating_add(hdr_sz));
+        if needed <= hdr_sz {
+            return Err(WriteGrantError::InsufficientSize);
+        }
+        // `needed` never exceeds `max` plus the header size, so this always fits
+        let hdr = H::try_from(needed - hdr_sz).unwrap_or(max);
+
+        let offset = self.bbq.cor.grant_exact(cap, needed)?;
+
+        let base_ptr = unsafe {
+            let p = ptr.as_ptr().byte_add(offset);
+            NonNull::new_unchecked(p)
+        };
+        Ok(FramedGrantW {
+            bbq: self.bbq.clone(),
+            base_ptr,
+            hdr,
+            written: 0,
+        })
+    }
+
+    /// The largest frame that [`FramedProducer::grant`] could provide right now
+    ///
+    /// Returns `None` if there is not even room for the header of an empty frame.
+    pub fn max_grant_len(&self) -> Option<H> {
+        let hdr_sz = const { core::mem::size_of::<H>() };
+        let max_grant = self.bbq.cor.snapshot().max_grant_len(self.capacity());
+        let frame = max_grant.checked_sub(hdr_sz)?;
+        Some(H::try_from(frame).unwrap_or(H::MAX))
+    }
+
+    /// The largest frame that could ever fit in this queue
+    ///
+    /// This is limited both by the capacity of the buffer, and by the largest
+    /// length that the header `H` can represent.
+    pub fn max_frame_len(&self) -> usize {
+        let hdr_sz = const { core::mem::size_of::<H>() };
+        let max_hdr: usize = H::MAX.into();
+        self.capacity().saturating_sub(hdr_sz).min(max_hdr)
+    }
+
+    /// Obtain a grant of up to `max` bytes, and fill it with `f`
+    ///
+    /// `f` returns the length of the frame, which is clamped to the length of
+    /// the grant, and also returned from this method. If `f` returns zero or
+    /// panics, the grant is aborted, and no frame is sent. See
+    /// [`FramedProducer::grant_max_remaining`] for the errors.
+    pub fn with_grant(
+        &self,
+        max: H,
+        f: impl FnOnce(&mut [u8]) -> usize,
+    ) -> Result<usize, WriteGrantError> {
+        let mut wgr = self.grant_max_remaining(max)?;
+        let used = f(&mut wgr).min(wgr.len());
+        if used == 0 {
+            wgr.abort();
+            return Ok(0);
+        }
+        wgr.commit_len(used);
+        Ok(used)
+    }
+
+    /// Copy `data` into the queue as a single frame
+    ///
+    /// Returns [`WriteGrantError::TooLarge`] if `data` does not fit in the header
+    /// `H`. See [`FramedProducer::grant`] for the other errors.
+    pub fn push_frame(&self, data: &[u8]) -> Result<(), WriteGrantError> {
+        let len = H::try_from(data.len()).map_err(|_| WriteGrantError::TooLarge)?;
+        let mut wgr = self.grant(len)?;
+        wgr.copy_from_slice(data);
+        wgr.commit(len);
+        Ok(())
+    }
+
+    /// Format `args` directly into a single frame
+    ///
+    /// Reserves the largest grant available, formats into it, and commits
+    /// exactly the formatted length, so `write!(prod, ...)` produces one frame
+    /// without an intermediate buffer. If the text does not fit, nothing is
+    /// committed and [`WriteGrantError::InsufficientSize`] is returned.
+    pub fn write_fmt(&self, args: fmt::Arguments<'_>) -> Result<(), WriteGrantError> {
+        let mut wgr = self.grant_max_remaining(H::MAX)?;
+        if fmt::Write::write_fmt(&mut wgr, args).is_err() {
+            wgr.abort();
+            return Err(WriteGrantError::InsufficientSize);
+        }
+        let used = wgr.written;
+        wgr.commit_len(used);
+        Ok(())
+    }
 }
 
 impl<Q, H> FramedProducer<Q, H>
@@ -157,14 +295,77 @@ where
 {
     /// Wait for the given write grant to become available
     ///
-    /// If `sz` is larger than the storage buffer, this method will never
-    /// return.
+    /// Returns an error if the queue is closed while waiting. Like
+    /// [`StreamProducer::wait_grant_exact`], this only returns immediately if the
+    /// frame is larger than the buffer.
+    ///
+    /// [`StreamProducer::wait_grant_exact`]: crate::prod_cons::stream::StreamProducer::wait_grant_exact
     ///
     /// The returned grant can be used to write up to `sz` bytes, though
     /// a smaller size may be committed. Dropping the grant without calling
     /// commit means that no data will be made visible to the consumer.
-    pub async fn wait_grant(&self, sz: H) -> FramedGrantW<Q, H> {
-        self.bbq.not.wait_for_not_full(|| self.grant(sz).ok()).await
+    pub async fn wait_grant(&self, sz: H) -> Result<FramedGrantW<Q, H>, WriteGrantError> {
+        self.bbq
+            .not
+            .wait_for_not_full(|| {
+                loop {
+                    match self.grant(sz) {
+                        Err(
+                            WriteGrantError::InsufficientSize | WriteGrantError::GrantInProgress,
+                        ) => {
+                            break None;
+                        }
+                        Err(WriteGrantError::WouldWrap) if !self.is_empty() => break None,
+                        // See `StreamProducer::wait_grant_exact`
+                        Err(WriteGrantError::WouldWrap) => core::hint::spin_loop(),
+                        res => break Some(res),
+                    }
+                }
+            })
+            .await
+    }
+
+    /// Copy `data` into the queue as a single frame, waiting for room as needed
+    ///
+    /// See [`FramedProducer::wait_grant`] for the errors.
+    pub async fn wait_push_frame(&self, data: &[u8]) -> Result<(), WriteGrantError> {
+        let len = H::try_from(data.len()).map_err(|_| WriteGrantError::TooLarge)?;
+        let mut wgr = self.wait_grant(len).await?;
+        wgr.copy_from_slice(data);
+        wgr.commit(len);
+        Ok(())
+    }
+
+    /// Wait for a grant of any size, up to `max`, to become available
+    ///
+    /// See [`FramedProducer::grant_max_remaining`] for details. Returns an error
+    /// if the queue is closed while waiting.
+    pub async fn wait_grant_max_remaining(
+        &self,
+        max: H,
+    ) -> Result<FramedGrantW<Q, H>, WriteGrantError> {
+        self.bbq
+            .not
+            .wait_for_not_full(|| match self.grant_max_remaining(max) {
+                Err(WriteGrantError::InsufficientSize | WriteGrantError::GrantInProgress) => None,
+                res => Some(res),
+            })
+            .await
+    }
+}
+
+impl<Q, H> Drop for FramedProducer<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    fn drop(&mut self) {
+        if self.taken {
+            if Q::CLOSE_ON_DROP {
+                self.close();
+            }
+            self.bbq.cor.give_back(Side::Producer);
+        }
     }
 }
 
@@ -222,6 +423,51 @@ where
             hdr,
         })
     }
+
+    /// Obtain the next frame, and process it with `f`
+    ///
+    /// The frame is released if `f` returns `true`, and kept in the queue to be
+    /// read again otherwise. If `f` panics, the frame is kept. Returns the value
+    /// returned by `f`. See [`FramedConsumer::read`] for the errors.
+    pub fn with_read(&self, f: impl FnOnce(&[u8]) -> bool) -> Result<bool, ReadGrantError> {
+        let rgr = self.read()?;
+        let release = f(&rgr);
+        if release {
+            rgr.release();
+        } else {
+            rgr.keep();
+        }
+        Ok(release)
+    }
+
+    /// Copy the next frame into `buf`, and release it
+    ///
+    /// Returns the length of the frame. If the frame is larger than `buf`, it is
+    /// kept in the queue, and [`ReadGrantError::BufferTooSmall`] is returned.
+    pub fn pop_frame_into(&self, buf: &mut [u8]) -> Result<usize, ReadGrantError> {
+        Self::copy_frame(self.read()?, buf)
+    }
+
+    fn copy_frame(rgr: FramedGrantR<Q, H>, buf: &mut [u8]) -> Result<usize, ReadGrantError> {
+        let len = rgr.len();
+        let Some(dst) = buf.get_mut(..len) else {
+            rgr.keep();
+            return Err(ReadGrantError::BufferTooSmall);
+        };
+        dst.copy_from_slice(&rgr);
+        rgr.release();
+        Ok(len)
+    }
+
+    /// The largest frame that could ever fit in this queue
+    ///
+    /// This is limited both by the capacity of the buffer, and by the largest
+    /// length that the header `H` can represent.
+    pub fn max_frame_len(&self) -> usize {
+        let hdr_sz = const { core::mem::size_of::<H>() };
+        let max_hdr: usize = H::MAX.into();
+        self.capacity().saturating_sub(hdr_sz).min(max_hdr)
+    }
 }
 
 impl<Q, H> FramedConsumer<Q, H>
@@ -230,8 +476,41 @@ where
     Q::Notifier: AsyncNotifier,
     H: LenHeader,
 {
-    pub async fn wait_read(&self) -> FramedGrantR<Q, H> {
-        self.bbq.not.wait_for_not_empty(|| self.read().ok()).await
+    /// Wait for a frame to become available
+    ///
+    /// Returns [`ReadGrantError::Closed`] once the queue has been closed and
+    /// all remaining frames have been read.
+    pub async fn wait_read(&self) -> Result<FramedGrantR<Q, H>, ReadGrantError> {
+        self.bbq
+            .not
+            .wait_for_not_empty(|| match self.read() {
+                Err(ReadGrantError::Empty | ReadGrantError::GrantInProgress) => None,
+                res => Some(res),
+            })
+            .await
+    }
+
+    /// Wait for a frame, copy it into `buf`, and release it
+    ///
+    /// See [`FramedConsumer::pop_frame_into`] and [`FramedConsumer::wait_read`]
+    /// for the errors.
+    pub async fn wait_pop_frame_into(&self, buf: &mut [u8]) -> Result<usize, ReadGrantError> {
+        Self::copy_frame(self.wait_read().await?, buf)
+    }
+}
+
+impl<Q, H> Drop for FramedConsumer<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    fn drop(&mut self) {
+        if self.taken {
+            if Q::CLOSE_ON_DROP {
+                self.close();
+            }
+            self.bbq.cor.give_back(Side::Consumer);
+        }
     }
 }
 
@@ -265,6 +544,20 @@ where
         core::mem::forget(self);
     }
 
+    /// Commit `used` bytes of the grant to be visible, see [`FramedGrantW::commit`]
+    ///
+    /// This is a convenience for when the number of bytes written was counted
+    /// as a `usize`. As with `commit`, `used` is clamped to the length of the grant.
+    pub fn commit_len(self, used: usize) {
+        let used = H::try_from(used).unwrap_or(self.hdr);
+        self.commit(used);
+    }
+
+    /// The number of bytes written with the [`fmt::Write`] impl so far
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
     /// Aborts the grant, making no frame available to the consumer
     ///
     /// Can be used to silence "must_use" errors.
@@ -306,6 +599,29 @@ where
     }
 }
 
+/// Formats text after any text written so far
+///
+/// If the text does not fit, as much of it as fits is written and an error
+/// is returned. Use [`FramedGrantW::written`] to commit the formatted text.
+impl<Q, H> fmt::Write for FramedGrantW<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let start = self.written;
+        let dst = &mut self[start..];
+        let n = dst.len().min(s.len());
+        dst[..n].copy_from_slice(&s.as_bytes()[..n]);
+        self.written += n;
+        if n == s.len() {
+            Ok(())
+        } else {
+            Err(fmt::Error)
+        }
+    }
+}
+
 impl<Q, H> Drop for FramedGrantW<Q, H>
 where
     Q: BbqHandle,
@@ -353,6 +669,23 @@ where
     pub fn keep(self) {
         // Default behavior is "keep"
     }
+
+    /// Settle the frame after failing to obtain a grant to copy it elsewhere
+    ///
+    /// A frame that can never fit is released, otherwise it is kept. Returns
+    /// `Ok(())` if the grant may succeed later, so the frame should be retried.
+    pub(crate) fn grant_failed(self, err: WriteGrantError) -> Result<(), WriteGrantError> {
+        match err {
+            WriteGrantError::InsufficientSize
+            | WriteGrantError::WouldWrap
+            | WriteGrantError::GrantInProgress => Ok(()),
+            WriteGrantError::TooLarge => {
+                self.release();
+                Err(err)
+            }
+            WriteGrantError::Closed => Err(err),
+        }
+    }
 }
 
 impl<Q, H> Deref for FramedGrantR<Q, H>
@@ -397,3 +730,172 @@ where
     H: LenHeader + Send,
 {
 }
+
+#[cfg(all(test, target_has_atomic = "ptr"))]
+mod test {
+    use core::ops::Deref;
+
+    use super::*;
+    use crate::{
+        queue::BBQueue,
+        traits::{coordination::cas::AtomicCoord, notifier::maitake::MaiNotSpsc, storage::Inline},
+    };
+
+    #[test]
+    fn occupancy_framed() {
+        use crate::{prod_cons::framed::FramedProducer, traits::notifier::blocking::Blocking};
+
+        static BBQ: BBQueue<Inline<16>, AtomicCoord, Blocking> = BBQueue::new();
+        let prod = BBQ.framed_producer();
+        let cons = BBQ.framed_consumer();
+
+        assert_eq!(prod.max_frame_len(), 14);
+        assert_eq!(prod.max_grant_len(), Some(14));
+        prod.grant(4).unwrap().commit(4);
+        assert_eq!(cons.len(), 6);
+        assert_eq!(prod.max_grant_len(), Some(8));
+
+        // An empty frame still needs room for its header
+        prod.grant(7).unwrap().commit(7);
+        assert_eq!(prod.max_grant_len(), None);
+
+        // The header limits the frame size for larger buffers
+        static BIG: BBQueue<Inline<100_000>, AtomicCoord, Blocking> = BBQueue::new();
+        let prod: FramedProducer<_, u16> = BIG.framed_producer();
+        assert_eq!(prod.max_frame_len(), u16::MAX as usize);
+    }
+
+    #[test]
+    fn framed_grant_max_remaining() {
+        use crate::traits::{coordination::WriteGrantError, notifier::blocking::Blocking};
+
+        static BBQ: BBQueue<Inline<16>, AtomicCoord, Blocking> = BBQueue::new();
+        let prod = BBQ.framed_producer();
+        let cons = BBQ.framed_consumer();
+
+        // Reserve everything, but only keep what was used
+        let mut wgr = prod.grant_max_remaining(u16::MAX).unwrap();
+        assert_eq!(wgr.len(), 14);
+        wgr[..3].copy_from_slice(&[1, 2, 3]);
+        wgr.commit(3);
+        assert_eq!(prod.len(), 5);
+
+        // Limited by `max`
+        let wgr = prod.grant_max_remaining(4).unwrap();
+        assert_eq!(wgr.len(), 4);
+        wgr.commit(4);
+
+        // Fill the tail, then use the space freed at the start
+        let rgr = cons.read().unwrap();
+        assert_eq!(rgr.deref(), &[1, 2, 3]);
+        rgr.release();
+        let wgr = prod.grant_max_remaining(u16::MAX).unwrap();
+        assert_eq!(wgr.len(), 3);
+        wgr.commit(3);
+        let wgr = prod.grant_max_remaining(u16::MAX).unwrap();
+        assert_eq!(wgr.len(), 2);
+        wgr.commit(2);
+        assert_eq!(
+            prod.grant_max_remaining(u16::MAX).err(),
+            Some(WriteGrantError::InsufficientSize)
+        );
+        assert_eq!(cons.read().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn format_grants() {
+        use crate::traits::{
+            coordination::WriteGrantError, mode::Framed, notifier::blocking::Blocking,
+        };
+        use core::fmt::Write;
+
+        static FRAMED: BBQueue<Inline<16>, AtomicCoord, Blocking, Framed> = BBQueue::new();
+        let (prod, cons) = FRAMED.split().unwrap();
+
+        write!(prod, "x={}", 42).unwrap();
+        assert_eq!(prod.len(), 6);
+        let rgr = cons.read().unwrap();
+        assert_eq!(rgr.deref(), b"x=42");
+        rgr.release();
+
+        // Does not fit, nothing is committed
+        assert_eq!(
+            write!(prod, "{:20}", 1),
+            Err(WriteGrantError::InsufficientSize)
+        );
+        assert!(prod.is_empty());
+
+        let mut wgr = prod.grant(4).unwrap();
+        assert!(write!(wgr, "hello").is_err());
+        assert_eq!(wgr.written(), 4);
+        wgr.commit(4);
+        assert_eq!(cons.read().unwrap().deref(), b"hell");
+    }
+
+    #[test]
+    fn closure_grants() {
+        use crate::traits::notifier::blocking::Blocking;
+
+        static FRAMED: BBQueue<Inline<16>, AtomicCoord, Blocking> = BBQueue::new();
+        let prod = FRAMED.framed_producer();
+        let cons = FRAMED.framed_consumer();
+
+        assert_eq!(prod.with_grant(8, |_| 0), Ok(0));
+        assert_eq!(
+            prod.with_grant(8, |buf| {
+                buf[..2].copy_from_slice(&[4, 5]);
+                2
+            }),
+            Ok(2)
+        );
+        assert_eq!(cons.with_read(|frame| frame == [9]), Ok(false));
+        assert_eq!(cons.with_read(|frame| frame == [4, 5]), Ok(true));
+        assert!(cons.is_empty());
+    }
+
+    #[tokio::test]
+    async fn never_fits() {
+        use crate::traits::coordination::WriteGrantError;
+
+        static BBQ: BBQueue<Inline<16>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        let prod: FramedProducer<_> = BBQ.framed_producer();
+
+        // With the header, only 14 bytes fit in a frame
+        assert_eq!(
+            prod.wait_grant(15).await.err(),
+            Some(WriteGrantError::TooLarge)
+        );
+        assert!(prod.wait_grant(14).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn copy_helpers() {
+        use crate::traits::coordination::ReadGrantError;
+
+        static FRAMED: BBQueue<Inline<16>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        let prod = FRAMED.framed_producer();
+        let cons = FRAMED.framed_consumer();
+
+        prod.push_frame(&[1, 2, 3]).unwrap();
+        let mut buf = [0; 4];
+        assert_eq!(
+            cons.pop_frame_into(&mut buf[..2]),
+            Err(ReadGrantError::BufferTooSmall)
+        );
+        assert_eq!(cons.pop_frame_into(&mut buf), Ok(3));
+        assert_eq!(&buf[..3], &[1, 2, 3]);
+        assert_eq!(cons.pop_frame_into(&mut buf), Err(ReadGrantError::Empty));
+
+        let rxfut = tokio::task::spawn(async move {
+            let mut buf = [0; 4];
+            for i in 0..10 {
+                assert_eq!(cons.wait_pop_frame_into(&mut buf).await, Ok(4));
+                assert_eq!(buf, [i; 4]);
+            }
+        });
+        for i in 0..10 {
+            prod.wait_push_frame(&[i; 4]).await.unwrap();
+        }
+        rxfut.await.unwrap();
+    }
+}
@@ -13,8 +13,246 @@
 //!   then a 30 byte grant, the consumer could potentially see all 60 bytes in a single read
 //!   grant (if there is no wrap-around).
 //!
+//! Framed data may also be sent with the handles in [`wrapping`](crate::prod_cons::wrapping),
+//! which allow frames to continue at the start of the buffer, instead of skipping the tail
+//! of the buffer when a frame does not fit there.
+//!
+//! Messages larger than the queue can be sent as a series of framed chunks, using the
+//! handles in [`fragmented`](crate::prod_cons::fragmented).
+//!
 //! You should NOT "mix and match" framed/stream consumers and producers. This will not cause
-//! memory safety/UB issues, but will not work properly.
+//! memory safety/UB issues, but will not work properly. The `try_*` and `split_*` methods
+//! of `BBQueue` track which handles have been taken, and refuse to hand out a second
+//! producer or consumer, or a mismatched pair of stream and framed handles. To catch this
+//! at compile time instead, fix the queue to the `Stream` or `Framed<H>` mode, see
+//! [`mode`](crate::traits::mode).
+//!
+//! Either side may `close()` the queue. Once closed, the producer can no longer obtain
+//! write grants, and the consumer can read any remaining data before observing
+//! `ReadGrantError::Closed`. Handles to an `ArcBBQueue` close the queue automatically
+//! when they are dropped.
 
+pub mod fragmented;
 pub mod framed;
 pub mod stream;
+pub mod wrapping;
+
+use crate::traits::{
+    bbqhdl::BbqHandle,
+    coordination::{ClearError, Coord, WriteGrantError},
+    notifier::{AsyncNotifier, Notifier},
+    storage::Storage,
+};
+use framed::{FramedConsumer, FramedProducer, LenHeader};
+use stream::{StreamConsumer, StreamProducer};
+use wrapping::{WrappingConsumer, WrappingProducer};
+
+// ---- Access to the queue from all handles ----
+
+macro_rules! impl_queue_access {
+    ($($handle:ident$(<$h:ident>)?),*) => {
+        $(
+            impl<Q $(, $h)?> $handle<Q $(, $h)?>
+            where
+                Q: BbqHandle,
+                $($h: LenHeader,)?
+            {
+                /// The total capacity of the buffer, in bytes
+                pub fn capacity(&self) -> usize {
+                    self.bbq.sto.ptr_len().1
+                }
+
+                /// The number of committed bytes not yet released by the consumer
+                ///
+                /// For framed queues, this includes the headers of each frame.
+                pub fn len(&self) -> usize {
+                    self.bbq.cor.snapshot().len()
+                }
+
+                /// The number of bytes not holding committed data
+                ///
+                /// Not all of this space may be usable for a single contiguous grant.
+                pub fn free(&self) -> usize {
+                    self.capacity() - self.len()
+                }
+
+                /// Is there no committed data waiting to be released by the consumer?
+                pub fn is_empty(&self) -> bool {
+                    self.bbq.cor.snapshot().is_empty()
+                }
+
+                /// Discard all data in the queue
+                ///
+                /// See [`BBQueue::clear`](crate::queue::BBQueue::clear) for details.
+                pub fn clear(&self) -> Result<(), ClearError> {
+                    self.bbq.clear()
+                }
+
+                /// Close the queue
+                ///
+                /// Future write grants fail with [`WriteGrantError::Closed`]. The
+                /// consumer may still read any data that was already committed, after
+                /// which it will observe
+                /// [`ReadGrantError::Closed`](crate::traits::coordination::ReadGrantError::Closed).
+                /// Waiting producers and consumers are woken.
+                pub fn close(&self) {
+                    if self.bbq.cor.close() {
+                        self.bbq.not.wake_all();
+                    }
+                }
+
+                /// Has the queue been closed by either side?
+                pub fn is_closed(&self) -> bool {
+                    self.bbq.cor.is_closed()
+                }
+            }
+        )*
+    };
+}
+
+impl_queue_access!(
+    StreamProducer,
+    StreamConsumer,
+    FramedProducer<H>,
+    FramedConsumer<H>,
+    WrappingProducer<H>,
+    WrappingConsumer<H>
+);
+
+// ---- Waiting for the consumer from all producers ----
+
+macro_rules! impl_wait_empty {
+    ($($handle:ident$(<$h:ident>)?),*) => {
+        $(
+            impl<Q $(, $h)?> $handle<Q $(, $h)?>
+            where
+                Q: BbqHandle,
+                $($h: LenHeader,)?
+            {
+                /// Spin until the consumer has released all committed data
+                ///
+                /// This is the blocking equivalent of [`Self::wait_empty`]. Returns an
+                /// error if the queue is closed before it has been drained.
+                pub fn wait_empty_blocking(&self) -> Result<(), WriteGrantError> {
+                    loop {
+                        if let Some(res) = self.poll_empty() {
+                            return res;
+                        }
+                        core::hint::spin_loop();
+                    }
+                }
+
+                fn poll_empty(&self) -> Option<Result<(), WriteGrantError>> {
+                    if self.bbq.cor.snapshot().is_empty() {
+                        Some(Ok(()))
+                    } else if self.bbq.cor.is_closed() {
+                        Some(Err(WriteGrantError::Closed))
+                    } else {
+                        None
+                    }
+                }
+            }
+
+            impl<Q $(, $h)?> $handle<Q $(, $h)?>
+            where
+                Q: BbqHandle,
+                Q::Notifier: AsyncNotifier,
+                $($h: LenHeader,)?
+            {
+                /// Wait until the consumer has released all committed data
+                ///
+                /// This can be used to make sure everything has been processed before
+                /// resetting a peripheral or powering down. Returns an error if the queue
+                /// is closed before it has been drained.
+                pub async fn wait_empty(&self) -> Result<(), WriteGrantError> {
+                    self.bbq.not.wait_for_not_full(|| self.poll_empty()).await
+                }
+            }
+        )*
+    };
+}
+
+impl_wait_empty!(StreamProducer, FramedProducer<H>, WrappingProducer<H>);
+
+#[cfg(all(test, target_has_atomic = "ptr"))]
+mod test {
+    use core::{ops::Deref, time::Duration};
+
+    use crate::{
+        queue::BBQueue,
+        traits::{coordination::cas::AtomicCoord, notifier::maitake::MaiNotSpsc, storage::Inline},
+    };
+
+    #[test]
+    fn close() {
+        use crate::traits::{
+            coordination::{ReadGrantError, WriteGrantError},
+            notifier::blocking::Blocking,
+        };
+
+        static BBQ: BBQueue<Inline<64>, AtomicCoord, Blocking> = BBQueue::new();
+        let prod = BBQ.stream_producer();
+        let cons = BBQ.stream_consumer();
+
+        let mut wgr = prod.grant_exact(4).unwrap();
+        wgr.copy_from_slice(&[1, 2, 3, 4]);
+        wgr.commit(4);
+        prod.close();
+
+        // No more writes, but remaining data can still be drained
+        assert!(cons.is_closed());
+        assert_eq!(prod.grant_exact(1).err(), Some(WriteGrantError::Closed));
+        let rgr = cons.read().unwrap();
+        assert_eq!(rgr.deref(), &[1, 2, 3, 4]);
+        rgr.release(4);
+        assert_eq!(cons.read().err(), Some(ReadGrantError::Closed));
+    }
+
+    #[tokio::test]
+    async fn wait_empty() {
+        static BBQ: BBQueue<Inline<64>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        let prod = BBQ.stream_producer();
+        let cons = BBQ.stream_consumer();
+
+        // Nothing committed yet
+        prod.wait_empty().await.unwrap();
+
+        let mut wgr = prod.grant_exact(4).unwrap();
+        wgr.copy_from_slice(&[1, 2, 3, 4]);
+        wgr.commit(4);
+
+        let rxfut = tokio::task::spawn(async move {
+            for _ in 0..2 {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                let rgr = cons.read().unwrap();
+                rgr.release(2);
+            }
+        });
+
+        tokio::time::timeout(Duration::from_secs(1), prod.wait_empty())
+            .await
+            .unwrap()
+            .unwrap();
+        rxfut.await.unwrap();
+    }
+
+    #[test]
+    fn wait_empty_blocking() {
+        use crate::traits::notifier::blocking::Blocking;
+
+        static BBQ: BBQueue<Inline<64>, AtomicCoord, Blocking> = BBQueue::new();
+        let prod = BBQ.framed_producer();
+        let cons = BBQ.framed_consumer();
+
+        let wgr = prod.grant(4).unwrap();
+        wgr.commit(4);
+
+        let rx = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            cons.read().unwrap().release();
+        });
+
+        prod.wait_empty_blocking().unwrap();
+        rx.join().unwrap();
+    }
+}
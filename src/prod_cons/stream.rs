@@ -6,13 +6,15 @@
 //! transfer.
 
 use core::{
+    fmt,
+    mem::ManuallyDrop,
     ops::{Deref, DerefMut},
     ptr::NonNull,
 };
 
 use crate::traits::{
     bbqhdl::BbqHandle,
-    coordination::{Coord, ReadGrantError, WriteGrantError},
+    coordination::{Coord, ReadGrantError, Side, WriteGrantError},
     notifier::{AsyncNotifier, Notifier},
     storage::Storage,
 };
@@ -23,6 +25,31 @@ where
     Q: BbqHandle,
 {
     pub(crate) bbq: Q::Target,
+    pub(crate) policy: NotifyPolicy,
+    /// Was this handle taken with [`Coord::take`], and must be given back?
+    pub(crate) taken: bool,
+}
+
+/// When a [`StreamProducer`] notifies the consumer about committed data
+///
+/// Waking the consumer on every commit can be expensive when data is produced
+/// a few bytes at a time, for example from a UART receive interrupt. Other
+/// policies allow for batching notifications. Regardless of the policy,
+/// [`StreamProducer::flush`] always notifies the consumer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NotifyPolicy {
+    /// Notify on every commit of a non-zero number of bytes
+    #[default]
+    Always,
+    /// Notify on commit once at least this many bytes are readable
+    ///
+    /// Values larger than the capacity of the queue are treated as the capacity,
+    /// as the queue could otherwise never hold enough data to notify.
+    AtLeast(usize),
+    /// Notify on commit once fewer than this many bytes are free
+    FreeBelow(usize),
+    /// Only notify when [`StreamProducer::flush`] is called
+    Manual,
 }
 
 /// A consumer handle that may read data from the buffer
@@ -31,11 +58,17 @@ where
     Q: BbqHandle,
 {
     pub(crate) bbq: Q::Target,
+    /// Was this handle taken with [`Coord::take`], and must be given back?
+    pub(crate) taken: bool,
 }
 
 /// A writing grant into the storage buffer
 ///
 /// Grants implement Deref/DerefMut to access the contained storage.
+///
+/// Dropping a grant commits the bytes before the write cursor, which is zero
+/// unless moved with [`StreamGrantW::write`], [`StreamGrantW::to_commit`], or
+/// similar.
 #[must_use = "Write Grants must be committed to be effective"]
 pub struct StreamGrantW<Q>
 where
@@ -45,6 +78,7 @@ where
     ptr: NonNull<u8>,
     len: usize,
     to_commit: usize,
+    policy: NotifyPolicy,
 }
 
 /// A reading grant into the storage buffer
@@ -53,6 +87,9 @@ where
 ///
 /// Write access is provided for read grants in case it is necessary to mutate
 /// the storage in-place for decoding.
+///
+/// Dropping a grant releases nothing, unless set with [`StreamGrantR::to_release`]
+/// or [`StreamGrantR::auto_release`].
 pub struct StreamGrantR<Q>
 where
     Q: BbqHandle,
@@ -63,8 +100,61 @@ where
     to_release: usize,
 }
 
+/// A write grant that has been detached from its queue with [`StreamGrantW::into_raw`]
+///
+/// The grant remains in progress until the token is passed back to
+/// [`StreamProducer::commit_raw`] or [`StreamProducer::grant_from_raw`].
+/// Dropping the token aborts the grant, after which the granted region must no
+/// longer be written.
+///
+/// The token holds a reference to the queue, so that the storage of the
+/// granted region stays alive while the token exists.
+#[must_use = "Dropping a raw grant token aborts the grant"]
+pub struct RawWriteToken<Q>
+where
+    Q: BbqHandle,
+{
+    bbq: Q::Target,
+    offset: usize,
+    len: usize,
+    to_commit: usize,
+}
+
+/// A read grant that has been detached from its queue with [`StreamGrantR::into_raw`]
+///
+/// The grant remains in progress until the token is passed back to
+/// [`StreamConsumer::release_raw`] or [`StreamConsumer::read_from_raw`].
+/// Dropping the token releases nothing, after which the granted region must no
+/// longer be read.
+///
+/// The token holds a reference to the queue, so that the storage of the
+/// granted region stays alive while the token exists.
+#[must_use = "Dropping a raw grant token releases nothing"]
+pub struct RawReadToken<Q>
+where
+    Q: BbqHandle,
+{
+    bbq: Q::Target,
+    offset: usize,
+    len: usize,
+    to_release: usize,
+}
+
 // ---- impls ----
 
+// ---- NotifyPolicy ----
+
+impl NotifyPolicy {
+    fn should_notify<C: Coord>(&self, cor: &C, capacity: usize) -> bool {
+        match *self {
+            NotifyPolicy::Always => true,
+            NotifyPolicy::AtLeast(n) => cor.snapshot().len() >= n.min(capacity),
+            NotifyPolicy::FreeBelow(n) => capacity - cor.snapshot().len() < n,
+            NotifyPolicy::Manual => false,
+        }
+    }
+}
+
 // ---- StreamProducer ----
 
 impl<Q> StreamProducer<Q>
@@ -98,6 +188,7 @@ where
             ptr,
             len,
             to_commit: 0,
+            policy: self.policy,
         })
     }
 
@@ -106,6 +197,13 @@ where
     /// Unlike `grant_max_remaining`, if there is insufficient size at the "tail" of
     /// the ring buffer, this method WILL cause a wrap-around to occur to attempt to
     /// find the requested write capacity.
+    ///
+    /// If the queue is empty, the grant starts at the beginning of the buffer.
+    ///
+    /// Returns [`WriteGrantError::TooLarge`] if `sz` exceeds the capacity of the
+    /// buffer, and [`WriteGrantError::WouldWrap`] if the grant only fits after a
+    /// wrap-around, but the consumer has not yet released enough data at the
+    /// start of the buffer.
     pub fn grant_exact(&self, sz: usize) -> Result<StreamGrantW<Q>, WriteGrantError> {
         let (ptr, cap) = self.bbq.sto.ptr_len();
         let offset = self.bbq.cor.grant_exact(cap, sz)?;
@@ -118,8 +216,152 @@ where
             ptr,
             len: sz,
             to_commit: 0,
+            policy: self.policy,
         })
     }
+
+    /// Re-attach a write grant that was detached with [`StreamGrantW::into_raw`]
+    ///
+    /// # Panics
+    ///
+    /// Panics if `token` was obtained from a grant of another queue.
+    ///
+    /// # Safety
+    ///
+    /// The pointer returned by [`StreamGrantW::into_raw`] must no longer be used.
+    pub unsafe fn grant_from_raw(&self, token: RawWriteToken<Q>) -> StreamGrantW<Q> {
+        assert!(
+            core::ptr::eq(&*token.bbq, &*self.bbq),
+            "raw grant token of another queue"
+        );
+        // Skip the Drop impl of the token, which would abort the grant
+        let token = ManuallyDrop::new(token);
+        let bbq = unsafe { core::ptr::read(&token.bbq) };
+        let (ptr, _cap) = bbq.sto.ptr_len();
+        let ptr = unsafe {
+            let p = ptr.as_ptr().byte_add(token.offset);
+            NonNull::new_unchecked(p)
+        };
+        StreamGrantW {
+            bbq,
+            ptr,
+            len: token.len,
+            to_commit: token.to_commit,
+            policy: self.policy,
+        }
+    }
+
+    /// Finish a write grant that was detached with [`StreamGrantW::into_raw`]
+    ///
+    /// This is the same as [`StreamGrantW::commit`], and may be called from
+    /// another context than the one that obtained the grant, such as a DMA
+    /// completion interrupt.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `token` was obtained from a grant of another queue.
+    ///
+    /// # Safety
+    ///
+    /// The pointer returned by [`StreamGrantW::into_raw`] must no longer be used.
+    pub unsafe fn commit_raw(&self, token: RawWriteToken<Q>, used: usize) {
+        unsafe { self.grant_from_raw(token) }.commit(used);
+    }
+
+    /// Obtain a grant of up to `max` bytes, and fill it with `f`
+    ///
+    /// `f` returns the number of bytes to commit, which is clamped to the length
+    /// of the grant, and also returned from this method. If `f` panics, the grant
+    /// is aborted. See [`StreamProducer::grant_max_remaining`] for the errors.
+    pub fn with_grant(
+        &self,
+        max: usize,
+        f: impl FnOnce(&mut [u8]) -> usize,
+    ) -> Result<usize, WriteGrantError> {
+        let mut wgr = self.grant_max_remaining(max)?;
+        let used = f(&mut wgr).min(wgr.len());
+        wgr.commit(used);
+        Ok(used)
+    }
+
+    /// Copy as much of `data` into the queue as currently fits
+    ///
+    /// Returns the number of bytes copied, which is zero if there is no room,
+    /// or the queue is closed. Unlike a single grant, this continues at the start
+    /// of the buffer when reaching the end of it.
+    pub fn push(&self, data: &[u8]) -> usize {
+        self.try_push(data).unwrap_or(0)
+    }
+
+    /// Copy all of `data` into the queue, or nothing at all
+    ///
+    /// Returns [`WriteGrantError::InsufficientSize`] if not all of `data` fits
+    /// right now, or [`WriteGrantError::TooLarge`] if it never could.
+    pub fn write_all(&self, data: &[u8]) -> Result<(), WriteGrantError> {
+        let cap = self.capacity();
+        if data.len() > cap {
+            return Err(WriteGrantError::TooLarge);
+        }
+        // See `Coord::snapshot` for when this stays valid
+        if self.bbq.cor.snapshot().max_push_len(cap) < data.len() {
+            return Err(WriteGrantError::InsufficientSize);
+        }
+        let mut remain = data;
+        while !remain.is_empty() {
+            let used = self.push_grant(remain)?;
+            remain = &remain[used..];
+        }
+        Ok(())
+    }
+
+    /// Like [`StreamProducer::push`], but reports why the first grant failed
+    fn try_push(&self, data: &[u8]) -> Result<usize, WriteGrantError> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+        let mut pushed = self.push_grant(data)?;
+        // Continue at the start of the buffer if we reached the end of it
+        while pushed < data.len() {
+            match self.push_grant(&data[pushed..]) {
+                Ok(used) => pushed += used,
+                Err(_) => break,
+            }
+        }
+        Ok(pushed)
+    }
+
+    /// Copy the start of `data` into a single grant, and commit it
+    fn push_grant(&self, data: &[u8]) -> Result<usize, WriteGrantError> {
+        let mut wgr = self.grant_max_remaining(data.len())?;
+        let used = wgr.len();
+        wgr.copy_from_slice(&data[..used]);
+        wgr.commit(used);
+        Ok(used)
+    }
+
+    /// The largest grant that [`StreamProducer::grant_exact`] could provide right now
+    pub fn max_grant_len(&self) -> usize {
+        self.bbq.cor.snapshot().max_grant_len(self.capacity())
+    }
+
+    /// Set the policy used to notify the consumer when data is committed
+    pub fn set_notify_policy(&mut self, policy: NotifyPolicy) {
+        self.policy = policy;
+    }
+
+    /// Replace the policy used to notify the consumer when data is committed
+    ///
+    /// This is a `const` variant of [`StreamProducer::set_notify_policy`], which
+    /// can be used when storing the producer in a `static`.
+    pub const fn with_notify_policy(mut self, policy: NotifyPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Notify the consumer that data is available, regardless of the policy
+    pub fn flush(&self) {
+        self.bbq.not.wake_one_consumer();
+    }
 }
 
 impl<Q> StreamProducer<Q>
@@ -128,22 +370,90 @@ where
     Q::Notifier: AsyncNotifier,
 {
     /// Wait for a grant of any size, up to `max`, to become available
-    pub async fn wait_grant_max_remaining(&self, max: usize) -> StreamGrantW<Q> {
+    ///
+    /// Returns an error if the queue is closed while waiting.
+    pub async fn wait_grant_max_remaining(
+        &self,
+        max: usize,
+    ) -> Result<StreamGrantW<Q>, WriteGrantError> {
         self.bbq
             .not
-            .wait_for_not_full(|| self.grant_max_remaining(max).ok())
+            .wait_for_not_full(|| match self.grant_max_remaining(max) {
+                Err(WriteGrantError::InsufficientSize | WriteGrantError::GrantInProgress) => None,
+                res => Some(res),
+            })
             .await
     }
 
     /// Wait for a grant of EXACTLY `sz` to become available.
     ///
-    /// If `sz` exceeds the capacity of the buffer, this method will never return.
-    pub async fn wait_grant_exact(&self, sz: usize) -> StreamGrantW<Q> {
+    /// Returns an error if the queue is closed while waiting.
+    ///
+    /// If `sz` exceeds the capacity of the buffer, [`WriteGrantError::TooLarge`]
+    /// is returned immediately. Grants that only fit after a wrap-around wait
+    /// until the consumer has released enough data.
+    pub async fn wait_grant_exact(&self, sz: usize) -> Result<StreamGrantW<Q>, WriteGrantError> {
+        self.bbq
+            .not
+            .wait_for_not_full(|| {
+                loop {
+                    match self.grant_exact(sz) {
+                        Err(
+                            WriteGrantError::InsufficientSize | WriteGrantError::GrantInProgress,
+                        ) => {
+                            break None;
+                        }
+                        Err(WriteGrantError::WouldWrap) if !self.is_empty() => break None,
+                        // An empty queue is only kept from rewinding while the consumer
+                        // briefly looks at the indices, which does not wake us
+                        Err(WriteGrantError::WouldWrap) => core::hint::spin_loop(),
+                        res => break Some(res),
+                    }
+                }
+            })
+            .await
+    }
+
+    /// Wait for room in the queue, and copy as much of `data` into it as fits
+    ///
+    /// Returns the number of bytes copied, which is only zero if `data` is empty.
+    /// Returns an error if the queue is closed while waiting.
+    pub async fn wait_push(&self, data: &[u8]) -> Result<usize, WriteGrantError> {
         self.bbq
             .not
-            .wait_for_not_full(|| self.grant_exact(sz).ok())
+            .wait_for_not_full(|| match self.try_push(data) {
+                Err(WriteGrantError::InsufficientSize | WriteGrantError::GrantInProgress) => None,
+                res => Some(res),
+            })
             .await
     }
+
+    /// Copy all of `data` into the queue, waiting for room as needed
+    ///
+    /// Returns an error if the queue is closed before all of `data` was copied,
+    /// in which case only the start of `data` was copied.
+    pub async fn wait_write_all(&self, data: &[u8]) -> Result<(), WriteGrantError> {
+        let mut remain = data;
+        while !remain.is_empty() {
+            let used = self.wait_push(remain).await?;
+            remain = &remain[used..];
+        }
+        Ok(())
+    }
+}
+
+impl<Q> Drop for StreamProducer<Q>
+where
+    Q: BbqHandle,
+{
+    fn drop(&mut self) {
+        if self.taken {
+            if Q::CLOSE_ON_DROP {
+                self.close();
+            }
+            self.bbq.cor.give_back(Side::Producer);
+        }
+    }
 }
 
 unsafe impl<Q: BbqHandle + Send> Send for StreamProducer<Q> {}
@@ -174,6 +484,175 @@ where
             to_release: 0,
         })
     }
+
+    /// Re-attach a read grant that was detached with [`StreamGrantR::into_raw`]
+    ///
+    /// # Panics
+    ///
+    /// Panics if `token` was obtained from a grant of another queue.
+    ///
+    /// # Safety
+    ///
+    /// The pointer returned by [`StreamGrantR::into_raw`] must no longer be used.
+    pub unsafe fn read_from_raw(&self, token: RawReadToken<Q>) -> StreamGrantR<Q> {
+        assert!(
+            core::ptr::eq(&*token.bbq, &*self.bbq),
+            "raw grant token of another queue"
+        );
+        // Skip the Drop impl of the token, which would end the grant
+        let token = ManuallyDrop::new(token);
+        let bbq = unsafe { core::ptr::read(&token.bbq) };
+        let (ptr, _cap) = bbq.sto.ptr_len();
+        let ptr = unsafe {
+            let p = ptr.as_ptr().byte_add(token.offset);
+            NonNull::new_unchecked(p)
+        };
+        StreamGrantR {
+            bbq,
+            ptr,
+            len: token.len,
+            to_release: token.to_release,
+        }
+    }
+
+    /// Finish a read grant that was detached with [`StreamGrantR::into_raw`]
+    ///
+    /// This is the same as [`StreamGrantR::release`], and may be called from
+    /// another context than the one that obtained the grant, such as a DMA
+    /// completion interrupt.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `token` was obtained from a grant of another queue.
+    ///
+    /// # Safety
+    ///
+    /// The pointer returned by [`StreamGrantR::into_raw`] must no longer be used.
+    pub unsafe fn release_raw(&self, token: RawReadToken<Q>, used: usize) {
+        unsafe { self.read_from_raw(token) }.release(used);
+    }
+
+    /// Obtain a read grant, and process it with `f`
+    ///
+    /// `f` returns the number of bytes to release, which is clamped to the length
+    /// of the grant, and also returned from this method. If `f` panics, nothing
+    /// is released. See [`StreamConsumer::read`] for the errors.
+    pub fn with_read(&self, f: impl FnOnce(&[u8]) -> usize) -> Result<usize, ReadGrantError> {
+        let rgr = self.read()?;
+        let used = f(&rgr).min(rgr.len());
+        rgr.release(used);
+        Ok(used)
+    }
+
+    /// Copy as much data out of the queue as fits in `buf`
+    ///
+    /// Returns the number of bytes copied, which is zero if the queue is empty.
+    /// Unlike a single read grant, this continues at the start of the buffer
+    /// when reaching the end of it.
+    pub fn pop_into(&self, buf: &mut [u8]) -> usize {
+        self.try_pop(buf).unwrap_or(0)
+    }
+
+    /// Fill all of `buf` with data from the queue, or copy nothing at all
+    ///
+    /// Returns [`ReadGrantError::InsufficientSize`] if fewer than `buf.len()`
+    /// bytes are available right now, or [`ReadGrantError::TooLarge`] if they
+    /// never could be.
+    pub fn read_exact_into(&self, buf: &mut [u8]) -> Result<(), ReadGrantError> {
+        if buf.len() > self.capacity() {
+            return Err(ReadGrantError::TooLarge);
+        }
+        // See `Coord::snapshot` for when this stays valid
+        if self.bbq.cor.snapshot().len() < buf.len() {
+            return Err(ReadGrantError::InsufficientSize);
+        }
+        let mut filled = 0;
+        while filled < buf.len() {
+            filled += self.pop_grant(&mut buf[filled..])?;
+        }
+        Ok(())
+    }
+
+    /// Like [`StreamConsumer::pop_into`], but reports why the first read failed
+    fn try_pop(&self, buf: &mut [u8]) -> Result<usize, ReadGrantError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut popped = self.pop_grant(buf)?;
+        // Continue at the start of the buffer if we reached the end of it
+        while popped < buf.len() {
+            match self.pop_grant(&mut buf[popped..]) {
+                Ok(used) => popped += used,
+                Err(_) => break,
+            }
+        }
+        Ok(popped)
+    }
+
+    /// Copy the start of a single read grant into `buf`, and release it
+    fn pop_grant(&self, buf: &mut [u8]) -> Result<usize, ReadGrantError> {
+        let rgr = self.read()?;
+        let used = rgr.len().min(buf.len());
+        buf[..used].copy_from_slice(&rgr[..used]);
+        rgr.release(used);
+        Ok(used)
+    }
+
+    /// The largest grant that [`StreamConsumer::read`] could provide right now
+    pub fn max_read_len(&self) -> usize {
+        self.bbq.cor.snapshot().max_read_len()
+    }
+
+    /// Obtain a chunk of readable data of at least `n` contiguous bytes
+    ///
+    /// This is useful for decoders that need a complete header before they can
+    /// make progress. In addition to the errors of [`StreamConsumer::read`], this
+    /// returns:
+    ///
+    /// * [`ReadGrantError::InsufficientSize`] if fewer than `n` contiguous bytes are
+    ///   available now, but more may become available once the producer commits
+    /// * [`ReadGrantError::Fragmented`] if `n` bytes can never become contiguous at
+    ///   the current read position, because the data wraps around the end of the
+    ///   ring buffer. The consumer must copy out and release the data before the
+    ///   wrap-around point to make progress
+    /// * [`ReadGrantError::TooLarge`] if `n` is larger than the capacity of the buffer
+    ///
+    /// Producers that write each message with a single `grant_exact` call never
+    /// split messages across the wrap-around point, so messages of up to `n` bytes
+    /// written this way never cause [`ReadGrantError::Fragmented`].
+    pub fn read_at_least(&self, n: usize) -> Result<StreamGrantR<Q>, ReadGrantError> {
+        let (ptr, cap) = self.bbq.sto.ptr_len();
+        if n > cap {
+            return Err(ReadGrantError::TooLarge);
+        }
+        let (offset, len) = self.bbq.cor.read()?;
+
+        if len < n {
+            // Release the grant without consuming anything, and figure out
+            // whether waiting could ever help
+            self.bbq.cor.release_inner(0);
+            let snap = self.bbq.cor.snapshot();
+            let inverted = snap.write < offset;
+            // When inverted, the readable region ends at `last`, and will not grow.
+            // Otherwise, it can only grow up to the end of the buffer.
+            return Err(if inverted || offset + n > cap {
+                ReadGrantError::Fragmented
+            } else {
+                ReadGrantError::InsufficientSize
+            });
+        }
+
+        let ptr = unsafe {
+            let p = ptr.as_ptr().byte_add(offset);
+            NonNull::new_unchecked(p)
+        };
+        Ok(StreamGrantR {
+            bbq: self.bbq.clone(),
+            ptr,
+            len,
+            to_release: 0,
+        })
+    }
 }
 
 impl<Q> StreamConsumer<Q>
@@ -182,8 +661,76 @@ where
     Q::Notifier: AsyncNotifier,
 {
     /// Wait for any read data to become available
-    pub async fn wait_read(&self) -> StreamGrantR<Q> {
-        self.bbq.not.wait_for_not_empty(|| self.read().ok()).await
+    ///
+    /// Returns [`ReadGrantError::Closed`] once the queue has been closed and
+    /// all remaining data has been read.
+    pub async fn wait_read(&self) -> Result<StreamGrantR<Q>, ReadGrantError> {
+        self.bbq
+            .not
+            .wait_for_not_empty(|| match self.read() {
+                Err(ReadGrantError::Empty | ReadGrantError::GrantInProgress) => None,
+                res => Some(res),
+            })
+            .await
+    }
+
+    /// Wait for at least `n` contiguous bytes of read data to become available
+    ///
+    /// This pairs well with a producer using [`NotifyPolicy::AtLeast`]. See
+    /// [`StreamConsumer::read_at_least`] for the errors that are returned
+    /// immediately instead of waiting. If the queue is closed before `n` bytes
+    /// become available, [`ReadGrantError::InsufficientSize`] is returned, and any
+    /// remaining data may still be obtained with [`StreamConsumer::read`].
+    pub async fn wait_read_at_least(&self, n: usize) -> Result<StreamGrantR<Q>, ReadGrantError> {
+        self.bbq
+            .not
+            .wait_for_not_empty(|| match self.read_at_least(n) {
+                Err(ReadGrantError::Empty | ReadGrantError::GrantInProgress) => None,
+                Err(ReadGrantError::InsufficientSize) if !self.bbq.cor.is_closed() => None,
+                res => Some(res),
+            })
+            .await
+    }
+
+    /// Wait for data, and copy as much of it out of the queue as fits in `buf`
+    ///
+    /// Returns the number of bytes copied, which is only zero if `buf` is empty.
+    /// Returns [`ReadGrantError::Closed`] once the queue has been closed and
+    /// all remaining data has been read.
+    pub async fn wait_pop_into(&self, buf: &mut [u8]) -> Result<usize, ReadGrantError> {
+        self.bbq
+            .not
+            .wait_for_not_empty(|| match self.try_pop(buf) {
+                Err(ReadGrantError::Empty | ReadGrantError::GrantInProgress) => None,
+                res => Some(res),
+            })
+            .await
+    }
+
+    /// Fill all of `buf` with data from the queue, waiting for data as needed
+    ///
+    /// Returns [`ReadGrantError::Closed`] if the queue is closed and drained
+    /// before `buf` was filled, in which case only the start of `buf` was filled.
+    pub async fn wait_read_exact_into(&self, buf: &mut [u8]) -> Result<(), ReadGrantError> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            filled += self.wait_pop_into(&mut buf[filled..]).await?;
+        }
+        Ok(())
+    }
+}
+
+impl<Q> Drop for StreamConsumer<Q>
+where
+    Q: BbqHandle,
+{
+    fn drop(&mut self) {
+        if self.taken {
+            if Q::CLOSE_ON_DROP {
+                self.close();
+            }
+            self.bbq.cor.give_back(Side::Consumer);
+        }
     }
 }
 
@@ -199,11 +746,93 @@ where
         let (_, cap) = self.bbq.sto.ptr_len();
         let used = used.min(self.len);
         self.bbq.cor.commit_inner(cap, self.len, used);
-        if used != 0 {
+        if used != 0 && self.policy.should_notify(&self.bbq.cor, cap) {
             self.bbq.not.wake_one_consumer();
         }
         core::mem::forget(self);
     }
+
+    /// Try to enlarge the grant in place by exactly `n` bytes
+    ///
+    /// This succeeds if the `n` bytes directly after the grant are free, for
+    /// example because the consumer has released data since the grant was
+    /// obtained. The grant never wraps around the end of the buffer. On failure,
+    /// the grant is left unchanged.
+    pub fn try_extend(&mut self, n: usize) -> Result<(), WriteGrantError> {
+        let (_, cap) = self.bbq.sto.ptr_len();
+        self.bbq.cor.grant_extend(cap, self.len, n)?;
+        self.len += n;
+        Ok(())
+    }
+
+    /// Set the number of bytes that will be committed when the grant is dropped
+    ///
+    /// By default, dropping a grant commits nothing. `n` is clamped to the
+    /// length of the grant. This also moves the write cursor to `n`.
+    pub fn to_commit(&mut self, n: usize) {
+        self.to_commit = n.min(self.len);
+    }
+
+    /// Detach the grant from the queue, e.g. to hand it to a DMA transfer
+    ///
+    /// Returns a pointer to and the length of the granted region, which remain
+    /// valid for writing until the token is passed back to the producer with
+    /// [`StreamProducer::commit_raw`] or [`StreamProducer::grant_from_raw`].
+    pub fn into_raw(self) -> (NonNull<u8>, usize, RawWriteToken<Q>) {
+        // Skip our Drop impl, which would finish the grant, and move our
+        // reference to the queue into the token
+        let this = ManuallyDrop::new(self);
+        let bbq = unsafe { core::ptr::read(&this.bbq) };
+        let (base, _cap) = bbq.sto.ptr_len();
+        let offset = unsafe { this.ptr.as_ptr().offset_from(base.as_ptr()) } as usize;
+        let token = RawWriteToken {
+            bbq,
+            offset,
+            len: this.len,
+            to_commit: this.to_commit,
+        };
+        (this.ptr, this.len, token)
+    }
+
+    /// Commit the whole grant when it is dropped
+    ///
+    /// This is useful when the entire grant is always filled, and early returns
+    /// should not discard the data. This also moves the write cursor to the end
+    /// of the grant.
+    pub fn auto_commit(&mut self) {
+        self.to_commit = self.len;
+    }
+
+    /// The position of the write cursor, which is the number of bytes that will
+    /// be committed when the grant is dropped
+    pub fn written(&self) -> usize {
+        self.to_commit
+    }
+
+    /// The part of the grant after the write cursor
+    pub fn unwritten_mut(&mut self) -> &mut [u8] {
+        let written = self.to_commit;
+        &mut self[written..]
+    }
+
+    /// Move the write cursor forward by `n` bytes, clamped to the end of the grant
+    ///
+    /// Use this after filling (part of) [`StreamGrantW::unwritten_mut`].
+    pub fn advance(&mut self, n: usize) {
+        self.to_commit = self.to_commit.saturating_add(n).min(self.len);
+    }
+
+    /// Copy as much of `data` as fits to the write cursor, and advance it
+    ///
+    /// Returns the number of bytes copied. Bytes written this way are committed
+    /// when the grant is dropped, so early returns do not discard them.
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        let dst = self.unwritten_mut();
+        let n = dst.len().min(data.len());
+        dst[..n].copy_from_slice(&data[..n]);
+        self.to_commit += n;
+        n
+    }
 }
 
 impl<Q> Deref for StreamGrantW<Q>
@@ -226,6 +855,23 @@ where
     }
 }
 
+/// Formats text to the write cursor, see [`StreamGrantW::write`]
+///
+/// If the text does not fit, as much of it as fits is written and an error
+/// is returned.
+impl<Q> fmt::Write for StreamGrantW<Q>
+where
+    Q: BbqHandle,
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.write(s.as_bytes()) == s.len() {
+            Ok(())
+        } else {
+            Err(fmt::Error)
+        }
+    }
+}
+
 impl<Q> Drop for StreamGrantW<Q>
 where
     Q: BbqHandle,
@@ -236,12 +882,13 @@ where
             ptr: _,
             len,
             to_commit,
+            policy,
         } = self;
         let (_, cap) = bbq.sto.ptr_len();
         let len = *len;
         let used = (*to_commit).min(len);
         bbq.cor.commit_inner(cap, len, used);
-        if used != 0 {
+        if used != 0 && policy.should_notify(&bbq.cor, cap) {
             bbq.not.wake_one_consumer();
         }
     }
@@ -263,6 +910,50 @@ where
         }
         core::mem::forget(self);
     }
+
+    /// Extend the grant to include data committed by the producer since the
+    /// grant was obtained
+    ///
+    /// The grant only grows up to the wrap-around point of the buffer. Returns
+    /// the new length of the grant.
+    pub fn refresh(&mut self) -> usize {
+        self.len = self.len.max(self.bbq.cor.read_refresh());
+        self.len
+    }
+
+    /// Set the number of bytes that will be released when the grant is dropped
+    ///
+    /// By default, dropping a grant releases nothing. `n` is clamped to the
+    /// length of the grant.
+    pub fn to_release(&mut self, n: usize) {
+        self.to_release = n.min(self.len);
+    }
+
+    /// Detach the grant from the queue, e.g. to hand it to a DMA transfer
+    ///
+    /// Returns a pointer to and the length of the granted region, which remain
+    /// valid until the token is passed back to the consumer with
+    /// [`StreamConsumer::release_raw`] or [`StreamConsumer::read_from_raw`].
+    pub fn into_raw(self) -> (NonNull<u8>, usize, RawReadToken<Q>) {
+        // Skip our Drop impl, which would finish the grant, and move our
+        // reference to the queue into the token
+        let this = ManuallyDrop::new(self);
+        let bbq = unsafe { core::ptr::read(&this.bbq) };
+        let (base, _cap) = bbq.sto.ptr_len();
+        let offset = unsafe { this.ptr.as_ptr().offset_from(base.as_ptr()) } as usize;
+        let token = RawReadToken {
+            bbq,
+            offset,
+            len: this.len,
+            to_release: this.to_release,
+        };
+        (this.ptr, this.len, token)
+    }
+
+    /// Release the whole grant when it is dropped
+    pub fn auto_release(&mut self) {
+        self.to_release = self.len;
+    }
 }
 
 impl<Q> Deref for StreamGrantR<Q>
@@ -306,3 +997,479 @@ where
 }
 
 unsafe impl<Q: BbqHandle + Send> Send for StreamGrantR<Q> {}
+
+// ---- RawWriteToken/RawReadToken ----
+
+impl<Q> Drop for RawWriteToken<Q>
+where
+    Q: BbqHandle,
+{
+    fn drop(&mut self) {
+        let (_, cap) = self.bbq.sto.ptr_len();
+        self.bbq.cor.commit_inner(cap, self.len, 0);
+    }
+}
+
+impl<Q> Drop for RawReadToken<Q>
+where
+    Q: BbqHandle,
+{
+    fn drop(&mut self) {
+        self.bbq.cor.release_inner(0);
+    }
+}
+
+#[cfg(all(test, target_has_atomic = "ptr"))]
+mod test {
+    use core::{ops::Deref, time::Duration};
+
+    use crate::{
+        queue::BBQueue,
+        traits::{coordination::cas::AtomicCoord, notifier::maitake::MaiNotSpsc, storage::Inline},
+    };
+
+    #[test]
+    fn read_at_least() {
+        use crate::traits::{coordination::ReadGrantError, notifier::blocking::Blocking};
+
+        static BBQ: BBQueue<Inline<16>, AtomicCoord, Blocking> = BBQueue::new();
+        let prod = BBQ.stream_producer();
+        let cons = BBQ.stream_consumer();
+
+        // Move the read and write positions close to the end of the buffer
+        prod.grant_exact(12).unwrap().commit(12);
+        cons.read().unwrap().release(12);
+        assert_eq!(cons.read_at_least(4).err(), Some(ReadGrantError::Empty));
+        assert_eq!(cons.read_at_least(17).err(), Some(ReadGrantError::TooLarge));
+
+        // Two bytes before the end, more could still arrive
+        prod.grant_exact(2).unwrap().commit(2);
+        assert_eq!(
+            cons.read_at_least(4).err(),
+            Some(ReadGrantError::InsufficientSize)
+        );
+
+        // The next write wraps around, the first two bytes will never become
+        // contiguous with the rest
+        prod.grant_exact(4).unwrap().commit(4);
+        assert_eq!(
+            cons.read_at_least(4).err(),
+            Some(ReadGrantError::Fragmented)
+        );
+        cons.read().unwrap().release(2);
+
+        let rgr = cons.read_at_least(4).unwrap();
+        assert_eq!(rgr.len(), 4);
+        rgr.release(4);
+    }
+
+    #[test]
+    fn occupancy() {
+        use crate::traits::notifier::blocking::Blocking;
+
+        static BBQ: BBQueue<Inline<16>, AtomicCoord, Blocking> = BBQueue::new();
+        let prod = BBQ.stream_producer();
+        let cons = BBQ.stream_consumer();
+
+        assert_eq!(prod.capacity(), 16);
+        assert!(prod.is_empty() && cons.is_empty());
+        assert_eq!(prod.max_grant_len(), 16);
+        assert_eq!(cons.max_read_len(), 0);
+
+        prod.grant_exact(10).unwrap().commit(10);
+        assert_eq!(cons.len(), 10);
+        assert_eq!(prod.free(), 6);
+        assert_eq!(cons.max_read_len(), 10);
+
+        cons.read().unwrap().release(8);
+        assert_eq!(prod.len(), 2);
+        // The tail is smaller than the space before the read position
+        assert_eq!(prod.max_grant_len(), 7);
+
+        // Wrap around, leaving two bytes before the end
+        prod.grant_exact(7).unwrap().commit(7);
+        assert_eq!(cons.len(), 9);
+        assert_eq!(cons.max_read_len(), 2);
+        assert_eq!(prod.max_grant_len(), 0);
+        cons.read().unwrap().release(2);
+        assert_eq!(cons.max_read_len(), 7);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn raw_grants() {
+        use std::sync::Mutex;
+
+        use crate::{
+            prod_cons::stream::{RawReadToken, RawWriteToken},
+            traits::{
+                coordination::{ReadGrantError, WriteGrantError},
+                notifier::blocking::Blocking,
+            },
+        };
+
+        type Queue = BBQueue<Inline<16>, AtomicCoord, Blocking>;
+        static BBQ: Queue = BBQueue::new();
+        static WRITE: Mutex<Option<RawWriteToken<&Queue>>> = Mutex::new(None);
+        static READ: Mutex<Option<RawReadToken<&Queue>>> = Mutex::new(None);
+        let prod = BBQ.stream_producer();
+        let cons = BBQ.stream_consumer();
+
+        // Start a "transfer", and finish it from another context
+        let (ptr, len, token) = prod.grant_exact(4).unwrap().into_raw();
+        assert_eq!(len, 4);
+        unsafe { core::ptr::copy_nonoverlapping([1, 2, 3, 4].as_ptr(), ptr.as_ptr(), len) };
+        *WRITE.lock().unwrap() = Some(token);
+        assert_eq!(
+            prod.grant_exact(1).err(),
+            Some(WriteGrantError::GrantInProgress)
+        );
+        std::thread::spawn(|| {
+            let token = WRITE.lock().unwrap().take().unwrap();
+            unsafe { BBQ.stream_producer().commit_raw(token, 3) };
+        })
+        .join()
+        .unwrap();
+
+        let (ptr, len, token) = cons.read().unwrap().into_raw();
+        assert_eq!(
+            unsafe { core::slice::from_raw_parts(ptr.as_ptr(), len) },
+            &[1, 2, 3]
+        );
+        *READ.lock().unwrap() = Some(token);
+        assert_eq!(cons.read().err(), Some(ReadGrantError::GrantInProgress));
+
+        // Grants can also be re-attached
+        let token = READ.lock().unwrap().take().unwrap();
+        let rgr = unsafe { cons.read_from_raw(token) };
+        assert_eq!(rgr.deref(), &[1, 2, 3]);
+        rgr.release(3);
+        assert!(cons.is_empty());
+
+        // Dropping a token aborts the grant
+        let (_, _, token) = prod.grant_exact(4).unwrap().into_raw();
+        drop(token);
+        prod.grant_exact(2).unwrap().commit(2);
+        let (_, _, token) = cons.read().unwrap().into_raw();
+        drop(token);
+        assert_eq!(cons.read().unwrap().len(), 2);
+        BBQ.clear().unwrap();
+
+        // Tokens only fit the queue they were obtained from
+        static OTHER: Queue = BBQueue::new();
+        let (_, _, token) = OTHER.stream_producer().grant_exact(4).unwrap().into_raw();
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+            prod.grant_from_raw(token)
+        }));
+        assert!(res.is_err());
+        assert!(OTHER.stream_producer().grant_exact(4).is_ok());
+
+        // The token keeps an Arc backed queue alive after all handles are gone
+        use crate::{queue::ArcBBQueue, traits::storage::BoxedSlice};
+
+        let bbq: ArcBBQueue<BoxedSlice, AtomicCoord, Blocking> =
+            ArcBBQueue::new_with_storage(BoxedSlice::new(16));
+        let prod = bbq.stream_producer();
+        let (ptr, len, token) = prod.grant_exact(4).unwrap().into_raw();
+        drop(prod);
+        drop(bbq);
+        unsafe { core::ptr::write_bytes(ptr.as_ptr(), 0xAA, len) };
+        drop(token);
+    }
+
+    #[test]
+    fn grow_grants() {
+        use crate::traits::{coordination::WriteGrantError, notifier::blocking::Blocking};
+
+        static BBQ: BBQueue<Inline<16>, AtomicCoord, Blocking> = BBQueue::new();
+        let prod = BBQ.stream_producer();
+        let cons = BBQ.stream_consumer();
+
+        // Read grants see data committed after they were obtained
+        prod.grant_exact(4).unwrap().commit(4);
+        let mut rgr = cons.read().unwrap();
+        assert_eq!(rgr.len(), 4);
+        prod.grant_exact(8).unwrap().commit(8);
+        assert_eq!(rgr.refresh(), 12);
+
+        // Write grants can only grow up to the end of the buffer
+        let mut wgr = prod.grant_max_remaining(2).unwrap();
+        assert_eq!(wgr.try_extend(2), Ok(()));
+        assert_eq!(wgr.len(), 4);
+        assert_eq!(wgr.try_extend(1), Err(WriteGrantError::InsufficientSize));
+        wgr.commit(4);
+        rgr.release(12);
+
+        // After wrapping around, they must stay behind the read position
+        let mut wgr = prod.grant_exact(4).unwrap();
+        assert_eq!(wgr.try_extend(8), Err(WriteGrantError::InsufficientSize));
+        assert_eq!(wgr.try_extend(7), Ok(()));
+        wgr.commit(11);
+
+        // Only the data up to the wrap-around point is visible
+        let mut rgr = cons.read().unwrap();
+        assert_eq!(rgr.len(), 4);
+        assert_eq!(rgr.refresh(), 4);
+        rgr.release(4);
+        assert_eq!(cons.read().unwrap().len(), 11);
+    }
+
+    #[test]
+    fn auto_commit() {
+        use crate::{
+            prod_cons::stream::StreamProducer,
+            traits::{coordination::WriteGrantError, notifier::blocking::Blocking},
+        };
+
+        type Queue = BBQueue<Inline<16>, AtomicCoord, Blocking>;
+        static BBQ: Queue = BBQueue::new();
+        let prod = BBQ.stream_producer();
+        let cons = BBQ.stream_consumer();
+
+        // Bytes written with the cursor survive an early return
+        fn encode(prod: &StreamProducer<&'static Queue>) -> Result<(), WriteGrantError> {
+            let mut wgr = prod.grant_exact(8)?;
+            assert_eq!(wgr.write(&[1, 2, 3]), 3);
+            wgr.unwritten_mut()[0] = 4;
+            wgr.advance(1);
+            assert_eq!(wgr.written(), 4);
+            Err(WriteGrantError::InsufficientSize)
+        }
+        assert!(encode(&prod).is_err());
+
+        let mut rgr = cons.read().unwrap();
+        assert_eq!(rgr.deref(), &[1, 2, 3, 4]);
+        rgr.to_release(2);
+        drop(rgr);
+        let mut rgr = cons.read().unwrap();
+        assert_eq!(rgr.deref(), &[3, 4]);
+        rgr.auto_release();
+        drop(rgr);
+        assert!(cons.is_empty());
+
+        // The cursor never passes the end of the grant
+        let mut wgr = prod.grant_exact(2).unwrap();
+        assert_eq!(wgr.write(&[5, 6, 7]), 2);
+        wgr.advance(10);
+        assert_eq!(wgr.written(), 2);
+        drop(wgr);
+
+        let mut wgr = prod.grant_exact(3).unwrap();
+        wgr.copy_from_slice(&[8, 9, 10]);
+        wgr.auto_commit();
+        drop(wgr);
+        assert_eq!(cons.read().unwrap().deref(), &[5, 6, 8, 9, 10]);
+    }
+
+    #[test]
+    fn format_grants() {
+        use crate::traits::notifier::blocking::Blocking;
+        use core::fmt::Write;
+
+        static STREAM: BBQueue<Inline<8>, AtomicCoord, Blocking> = BBQueue::new();
+        let prod = STREAM.stream_producer();
+        let cons = STREAM.stream_consumer();
+
+        let mut wgr = prod.grant_exact(6).unwrap();
+        write!(wgr, "{}-", 12).unwrap();
+        assert!(write!(wgr, "abcd").is_err());
+        drop(wgr);
+        let rgr = cons.read().unwrap();
+        assert_eq!(rgr.deref(), b"12-abc");
+        rgr.release(6);
+    }
+
+    #[test]
+    fn closure_grants() {
+        use crate::traits::notifier::blocking::Blocking;
+        use std::panic::{AssertUnwindSafe, catch_unwind};
+
+        static STREAM: BBQueue<Inline<16>, AtomicCoord, Blocking> = BBQueue::new();
+        let prod = STREAM.stream_producer();
+        let cons = STREAM.stream_consumer();
+
+        let res = prod.with_grant(8, |buf| {
+            buf[..3].copy_from_slice(&[1, 2, 3]);
+            3
+        });
+        assert_eq!(res, Ok(3));
+        assert_eq!(prod.with_grant(4, |_| 10), Ok(4));
+        assert_eq!(cons.with_read(|buf| buf.len().min(2)), Ok(2));
+
+        // A panic aborts the grant, and the queue is still usable
+        let res = catch_unwind(AssertUnwindSafe(|| prod.with_grant(4, |_| panic!())));
+        assert!(res.is_err());
+        let res = catch_unwind(AssertUnwindSafe(|| cons.with_read(|_| panic!())));
+        assert!(res.is_err());
+        assert_eq!(prod.len(), 5);
+        assert_eq!(cons.with_read(|buf| buf.len()), Ok(5));
+    }
+
+    #[tokio::test]
+    async fn never_fits() {
+        use crate::traits::coordination::WriteGrantError;
+
+        static BBQ: BBQueue<Inline<16>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        let prod = BBQ.stream_producer();
+        let cons = BBQ.stream_consumer();
+
+        let timeout = |fut| tokio::time::timeout(Duration::from_secs(1), fut);
+        assert_eq!(
+            timeout(prod.wait_grant_exact(17)).await.unwrap().err(),
+            Some(WriteGrantError::TooLarge)
+        );
+
+        // Move the read and write positions to the middle of the buffer. The
+        // tail is too short, and wrapping around would pass the read position.
+        prod.grant_exact(10).unwrap().commit(10);
+        assert_eq!(prod.grant_exact(12).err(), Some(WriteGrantError::WouldWrap));
+        cons.read().unwrap().release(10);
+
+        // Once the queue is empty, the grant starts over at the beginning
+        timeout(prod.wait_grant_exact(12))
+            .await
+            .unwrap()
+            .unwrap()
+            .commit(12);
+        assert_eq!(cons.read().unwrap().deref(), &[0u8; 12]);
+    }
+
+    #[test]
+    fn rewind() {
+        use crate::traits::{coordination::WriteGrantError, notifier::blocking::Blocking};
+
+        // Grants larger than half of the queue, which never fit at the end of
+        // the buffer after the previous one
+        static BBQ: BBQueue<Inline<64>, AtomicCoord, Blocking> = BBQueue::new();
+        let prod = BBQ.stream_producer();
+        let cons = BBQ.stream_consumer();
+        for i in 0..4u8 {
+            let mut wgr = prod.grant_exact(40).unwrap();
+            wgr.fill(i);
+            wgr.commit(40);
+            assert_eq!(prod.grant_exact(40).err(), Some(WriteGrantError::WouldWrap));
+            let rgr = cons.read().unwrap();
+            assert_eq!(rgr.deref(), &[i; 40]);
+            rgr.release(40);
+        }
+
+        // Also after both sides have wrapped around, with the write position
+        // in the middle of the buffer
+        prod.grant_exact(20).unwrap().commit(20);
+        prod.grant_exact(20).unwrap().commit(20);
+        cons.read().unwrap().release(20);
+        cons.read().unwrap().release(20);
+        prod.grant_exact(50).unwrap().commit(50);
+        assert_eq!(cons.read().unwrap().len(), 50);
+    }
+
+    #[tokio::test]
+    async fn copy_helpers() {
+        use crate::traits::coordination::{ReadGrantError, WriteGrantError};
+
+        static STREAM: BBQueue<Inline<8>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        let prod = STREAM.stream_producer();
+        let cons = STREAM.stream_consumer();
+
+        // Move to the middle, so copies wrap around the end of the buffer
+        assert_eq!(prod.push(&[0; 5]), 5);
+        assert_eq!(cons.pop_into(&mut [0; 5]), 5);
+
+        assert_eq!(prod.push(&[1, 2, 3, 4, 5, 6, 7, 8, 9]), 7);
+        let mut buf = [0; 4];
+        assert_eq!(cons.read_exact_into(&mut buf), Ok(()));
+        assert_eq!(buf, [1, 2, 3, 4]);
+        assert_eq!(cons.pop_into(&mut buf), 3);
+        assert_eq!(&buf[..3], &[5, 6, 7]);
+
+        assert_eq!(prod.write_all(&[0; 9]), Err(WriteGrantError::TooLarge));
+        assert_eq!(prod.write_all(&[1; 6]), Ok(()));
+        assert_eq!(
+            prod.write_all(&[2; 2]),
+            Err(WriteGrantError::InsufficientSize)
+        );
+        assert_eq!(prod.len(), 6);
+        assert_eq!(
+            cons.read_exact_into(&mut [0; 7]),
+            Err(ReadGrantError::InsufficientSize)
+        );
+        assert_eq!(cons.pop_into(&mut [0; 8]), 6);
+
+        let msg: [u8; 40] = core::array::from_fn(|i| i as u8);
+        let txfut = tokio::task::spawn(async move {
+            prod.wait_write_all(&msg).await.unwrap();
+        });
+        let mut buf = [0; 40];
+        cons.wait_read_exact_into(&mut buf).await.unwrap();
+        assert_eq!(buf, msg);
+        txfut.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn notify_policy() {
+        use crate::prod_cons::stream::NotifyPolicy;
+
+        static BBQ: BBQueue<Inline<64>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        let prod = BBQ
+            .stream_producer()
+            .with_notify_policy(NotifyPolicy::AtLeast(4));
+        let cons = BBQ.stream_consumer();
+
+        let rxfut = tokio::task::spawn(async move {
+            let rgr = cons.wait_read_at_least(4).await.unwrap();
+            assert_eq!(rgr.deref(), &[0, 1, 2, 3]);
+            rgr.release(4);
+            cons
+        });
+
+        for i in 0..4 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let mut wgr = prod.grant_exact(1).unwrap();
+            wgr[0] = i;
+            wgr.commit(1);
+        }
+        let cons = tokio::time::timeout(Duration::from_secs(1), rxfut)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // With a manual policy, the consumer is only woken on flush
+        let mut prod = prod;
+        prod.set_notify_policy(NotifyPolicy::Manual);
+        let mut rxfut = tokio::task::spawn(async move {
+            let rgr = cons.wait_read().await.unwrap();
+            assert_eq!(rgr.deref(), &[4]);
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let mut wgr = prod.grant_exact(1).unwrap();
+        wgr[0] = 4;
+        wgr.commit(1);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), &mut rxfut)
+                .await
+                .is_err()
+        );
+        prod.flush();
+        tokio::time::timeout(Duration::from_secs(1), rxfut)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // A threshold above the capacity notifies once the queue is full
+        static SMALL: BBQueue<Inline<8>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        let prod = SMALL
+            .stream_producer()
+            .with_notify_policy(NotifyPolicy::AtLeast(100));
+        let rxfut = tokio::task::spawn(async move {
+            let cons = SMALL.stream_consumer();
+            let rgr = cons.wait_read().await.unwrap();
+            assert_eq!(rgr.len(), 8);
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        prod.grant_exact(8).unwrap().commit(8);
+        tokio::time::timeout(Duration::from_secs(1), rxfut)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -0,0 +1,578 @@
+//! Wrapping framed byte queue interfaces
+//!
+//! Like the [`framed`](super::framed) interfaces, but a frame may continue at the
+//! start of the buffer, instead of skipping the tail of the buffer when the frame
+//! does not fit there. This wastes less space when frames of varying sizes are
+//! used with small buffers, at the cost of every grant consisting of up to two
+//! slices.
+//!
+//! The contents of the queue are not compatible with the other producers and
+//! consumers, so wrapping handles may only be paired with each other.
+
+use core::{marker::PhantomData, mem::MaybeUninit, ptr::NonNull};
+
+use crate::traits::{
+    bbqhdl::BbqHandle,
+    coordination::{Coord, ReadGrantError, Side, WriteGrantError},
+    notifier::{AsyncNotifier, Notifier},
+    storage::Storage,
+};
+
+use super::framed::LenHeader;
+
+/// A producer handle that can be used to write framed chunks, which may wrap
+/// around the end of the buffer
+pub struct WrappingProducer<Q, H = u16>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    pub(crate) bbq: Q::Target,
+    pub(crate) pd: PhantomData<H>,
+    /// Was this handle taken with [`Coord::take`], and must be given back?
+    pub(crate) taken: bool,
+}
+
+/// A consumer handle that can be used to read framed chunks, which may wrap
+/// around the end of the buffer
+pub struct WrappingConsumer<Q, H = u16>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    pub(crate) bbq: Q::Target,
+    pub(crate) pd: PhantomData<H>,
+    /// Was this handle taken with [`Coord::take`], and must be given back?
+    pub(crate) taken: bool,
+}
+
+/// A writing grant into the storage buffer
+///
+/// The body of the grant consists of up to two slices, see
+/// [`WrappingGrantW::as_mut_slices`].
+#[must_use = "Write Grants must be committed to be effective"]
+pub struct WrappingGrantW<Q, H = u16>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    bbq: Q::Target,
+    start: usize,
+    hdr: H,
+}
+
+/// A reading grant into the storage buffer
+///
+/// The body of the grant consists of up to two slices, see
+/// [`WrappingGrantR::as_slices`].
+#[must_use = "Read Grants must be released to free space"]
+pub struct WrappingGrantR<Q, H = u16>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    bbq: Q::Target,
+    start: usize,
+    hdr: H,
+}
+
+// ---- helpers ----
+
+/// The offsets and lengths of the parts of `len` bytes at `offset`, before and
+/// after the end of a buffer of `cap` bytes
+fn split(offset: usize, len: usize, cap: usize) -> ((usize, usize), (usize, usize)) {
+    let offset = if offset >= cap { offset - cap } else { offset };
+    let first = len.min(cap - offset);
+    ((offset, first), (0, len - first))
+}
+
+/// Copy `src` into the buffer at `offset`, continuing at the start of the buffer
+///
+/// # Safety
+///
+/// The region must be within a live write grant
+unsafe fn write_split(base: NonNull<u8>, cap: usize, offset: usize, src: &[u8]) {
+    let ((off1, len1), (off2, len2)) = split(offset, src.len(), cap);
+    unsafe {
+        let base = base.as_ptr();
+        core::ptr::copy_nonoverlapping(src.as_ptr(), base.add(off1), len1);
+        core::ptr::copy_nonoverlapping(src.as_ptr().add(len1), base.add(off2), len2);
+    }
+}
+
+/// Copy from the buffer at `offset` into `dst`, continuing at the start of the buffer
+///
+/// # Safety
+///
+/// The region must be within a live read grant
+unsafe fn read_split(base: NonNull<u8>, cap: usize, offset: usize, dst: &mut [u8]) {
+    let ((off1, len1), (off2, len2)) = split(offset, dst.len(), cap);
+    unsafe {
+        let base = base.as_ptr();
+        core::ptr::copy_nonoverlapping(base.add(off1), dst.as_mut_ptr(), len1);
+        core::ptr::copy_nonoverlapping(base.add(off2), dst.as_mut_ptr().add(len1), len2);
+    }
+}
+
+/// Write a header, which may itself be split at the end of the buffer
+///
+/// # Safety
+///
+/// The region must be within a live write grant
+unsafe fn write_hdr<H: LenHeader>(base: NonNull<u8>, cap: usize, offset: usize, hdr: H) {
+    let hdr_sz = const { core::mem::size_of::<H>() };
+    // Headers are stored in native layout, like in the framed interfaces
+    let bytes = unsafe { core::slice::from_raw_parts((&raw const hdr).cast::<u8>(), hdr_sz) };
+    unsafe { write_split(base, cap, offset, bytes) };
+}
+
+/// Read a header, which may itself be split at the end of the buffer
+///
+/// # Safety
+///
+/// The region must be within a live read grant
+unsafe fn read_hdr<H: LenHeader>(base: NonNull<u8>, cap: usize, offset: usize) -> H {
+    let hdr_sz = const { core::mem::size_of::<H>() };
+    let mut hdr = MaybeUninit::<H>::uninit();
+    unsafe {
+        let bytes = core::slice::from_raw_parts_mut(hdr.as_mut_ptr().cast::<u8>(), hdr_sz);
+        read_split(base, cap, offset, bytes);
+        hdr.assume_init()
+    }
+}
+
+// ---- impl WrappingProducer ----
+
+impl<Q, H> WrappingProducer<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    /// Attempt to obtain a write grant of the given (max) size
+    ///
+    /// The returned grant can be used to write up to `sz` bytes, though
+    /// a smaller size may be committed. Dropping the grant without calling
+    /// commit means that no data will be made visible to the consumer.
+    ///
+    /// Returns [`WriteGrantError::TooLarge`] if the frame and its header exceed
+    /// the capacity of the buffer.
+    pub fn grant(&self, sz: H) -> Result<WrappingGrantW<Q, H>, WriteGrantError> {
+        let (_ptr, cap) = self.bbq.sto.ptr_len();
+        let needed = sz
+            .into()
+            .checked_add(core::mem::size_of::<H>())
+            .ok_or(WriteGrantError::TooLarge)?;
+
+        let start = self.bbq.cor.grant_wrapping(cap, needed)?;
+
+        Ok(WrappingGrantW {
+            bbq: self.bbq.clone(),
+            start,
+            hdr: sz,
+        })
+    }
+
+    /// The largest frame that could ever fit in this queue
+    ///
+    /// This is limited both by the capacity of the buffer, and by the largest
+    /// length that the header `H` can represent.
+    pub fn max_frame_len(&self) -> usize {
+        let hdr_sz = const { core::mem::size_of::<H>() };
+        let max_hdr: usize = H::MAX.into();
+        self.capacity().saturating_sub(hdr_sz).min(max_hdr)
+    }
+}
+
+impl<Q, H> WrappingProducer<Q, H>
+where
+    Q: BbqHandle,
+    Q::Notifier: AsyncNotifier,
+    H: LenHeader,
+{
+    /// Wait for the given write grant to become available
+    ///
+    /// Returns an error if the queue is closed while waiting, or immediately if
+    /// the frame is larger than the buffer.
+    pub async fn wait_grant(&self, sz: H) -> Result<WrappingGrantW<Q, H>, WriteGrantError> {
+        self.bbq
+            .not
+            .wait_for_not_full(|| {
+                loop {
+                    match self.grant(sz) {
+                        Err(
+                            WriteGrantError::InsufficientSize | WriteGrantError::GrantInProgress,
+                        ) => {
+                            break None;
+                        }
+                        Err(WriteGrantError::WouldWrap) if !self.is_empty() => break None,
+                        // See `StreamProducer::wait_grant_exact`
+                        Err(WriteGrantError::WouldWrap) => core::hint::spin_loop(),
+                        res => break Some(res),
+                    }
+                }
+            })
+            .await
+    }
+}
+
+impl<Q, H> Drop for WrappingProducer<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    fn drop(&mut self) {
+        if self.taken {
+            if Q::CLOSE_ON_DROP {
+                self.close();
+            }
+            self.bbq.cor.give_back(Side::Producer);
+        }
+    }
+}
+
+// ---- impl WrappingConsumer ----
+
+impl<Q, H> WrappingConsumer<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    /// Attempt to receive a frame
+    pub fn read(&self) -> Result<WrappingGrantR<Q, H>, ReadGrantError> {
+        let (ptr, cap) = self.bbq.sto.ptr_len();
+        let (start, first, second) = self.bbq.cor.read_wrapping()?;
+
+        // Data only continues at the start of the buffer if the readable region
+        // reaches the end of the buffer
+        let avail = if start + first == cap {
+            first + second
+        } else {
+            first
+        };
+
+        let hdr_sz = const { core::mem::size_of::<H>() };
+        if hdr_sz > avail {
+            // A partial header, this should only be possible if a different
+            // kind of producer was used
+            self.bbq.cor.release_wrapping(0);
+            return Err(ReadGrantError::InconsistentFrameHeader);
+        }
+
+        let hdr: H = unsafe { read_hdr(ptr, cap, start) };
+        if (hdr_sz + hdr.into()) > avail {
+            // Again, the header value + header size are larger than the
+            // readable region, this means someone is doing something sketch
+            self.bbq.cor.release_wrapping(0);
+            return Err(ReadGrantError::InconsistentFrameHeader);
+        }
+
+        Ok(WrappingGrantR {
+            bbq: self.bbq.clone(),
+            start,
+            hdr,
+        })
+    }
+}
+
+impl<Q, H> WrappingConsumer<Q, H>
+where
+    Q: BbqHandle,
+    Q::Notifier: AsyncNotifier,
+    H: LenHeader,
+{
+    /// Wait for a frame to become available
+    ///
+    /// Returns [`ReadGrantError::Closed`] once the queue has been closed and
+    /// all remaining frames have been read.
+    pub async fn wait_read(&self) -> Result<WrappingGrantR<Q, H>, ReadGrantError> {
+        self.bbq
+            .not
+            .wait_for_not_empty(|| match self.read() {
+                Err(ReadGrantError::Empty | ReadGrantError::GrantInProgress) => None,
+                res => Some(res),
+            })
+            .await
+    }
+}
+
+impl<Q, H> Drop for WrappingConsumer<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    fn drop(&mut self) {
+        if self.taken {
+            if Q::CLOSE_ON_DROP {
+                self.close();
+            }
+            self.bbq.cor.give_back(Side::Consumer);
+        }
+    }
+}
+
+// ---- impl WrappingGrantW ----
+
+impl<Q, H> WrappingGrantW<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    /// The length of the body of the grant
+    pub fn len(&self) -> usize {
+        self.hdr.into()
+    }
+
+    /// Is the body of the grant empty?
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The body of the grant, as the parts before and after the end of the buffer
+    ///
+    /// The second slice is empty unless the grant wraps around.
+    pub fn as_mut_slices(&mut self) -> (&mut [u8], &mut [u8]) {
+        let (ptr, cap) = self.bbq.sto.ptr_len();
+        let hdr_sz = const { core::mem::size_of::<H>() };
+        let ((off1, len1), (off2, len2)) = split(self.start + hdr_sz, self.len(), cap);
+        unsafe {
+            let base = ptr.as_ptr();
+            (
+                core::slice::from_raw_parts_mut(base.add(off1), len1),
+                core::slice::from_raw_parts_mut(base.add(off2), len2),
+            )
+        }
+    }
+
+    /// Copy as much of `data` as fits to the start of the body
+    ///
+    /// Returns the number of bytes copied.
+    pub fn copy_from(&mut self, data: &[u8]) -> usize {
+        let (ptr, cap) = self.bbq.sto.ptr_len();
+        let hdr_sz = const { core::mem::size_of::<H>() };
+        let n = data.len().min(self.len());
+        unsafe { write_split(ptr, cap, self.start + hdr_sz, &data[..n]) };
+        n
+    }
+
+    /// Commit `used` bytes of the grant to be visible.
+    ///
+    /// If `used` is greater than the `sz` used to create this grant, the
+    /// amount will be clamped to `sz`.
+    pub fn commit(self, used: H) {
+        let (ptr, cap) = self.bbq.sto.ptr_len();
+        let hdrlen: usize = const { core::mem::size_of::<H>() };
+        let clamp_hdr = self.hdr.min(used);
+        let used_len: usize = hdrlen + clamp_hdr.into();
+
+        unsafe { write_hdr(ptr, cap, self.start, clamp_hdr) };
+
+        self.bbq.cor.commit_wrapping(cap, self.start, used_len);
+        self.bbq.not.wake_one_consumer();
+        core::mem::forget(self);
+    }
+
+    /// Aborts the grant, making no frame available to the consumer
+    ///
+    /// Can be used to silence "must_use" errors.
+    pub fn abort(self) {
+        // The default behavior is to abort - do nothing, let the
+        // drop impl run
+    }
+}
+
+impl<Q, H> Drop for WrappingGrantW<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    fn drop(&mut self) {
+        // Default drop performs an "abort"
+        let (_ptr, cap) = self.bbq.sto.ptr_len();
+        self.bbq.cor.commit_wrapping(cap, self.start, 0);
+    }
+}
+
+unsafe impl<Q, H> Send for WrappingGrantW<Q, H>
+where
+    Q: BbqHandle,
+    Q::Target: Send,
+    H: LenHeader + Send,
+{
+}
+
+// ---- impl WrappingGrantR ----
+
+impl<Q, H> WrappingGrantR<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    /// The length of the body of the grant
+    pub fn len(&self) -> usize {
+        self.hdr.into()
+    }
+
+    /// Is the body of the grant empty?
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The body of the grant, as the parts before and after the end of the buffer
+    ///
+    /// The second slice is empty unless the frame wraps around.
+    pub fn as_slices(&self) -> (&[u8], &[u8]) {
+        let (ptr, cap) = self.bbq.sto.ptr_len();
+        let hdr_sz = const { core::mem::size_of::<H>() };
+        let ((off1, len1), (off2, len2)) = split(self.start + hdr_sz, self.len(), cap);
+        unsafe {
+            let base = ptr.as_ptr();
+            (
+                core::slice::from_raw_parts(base.add(off1), len1),
+                core::slice::from_raw_parts(base.add(off2), len2),
+            )
+        }
+    }
+
+    /// The body of the grant, as the parts before and after the end of the buffer
+    ///
+    /// Write access is provided in case it is necessary to mutate the storage
+    /// in-place for decoding.
+    pub fn as_mut_slices(&mut self) -> (&mut [u8], &mut [u8]) {
+        let (ptr, cap) = self.bbq.sto.ptr_len();
+        let hdr_sz = const { core::mem::size_of::<H>() };
+        let ((off1, len1), (off2, len2)) = split(self.start + hdr_sz, self.len(), cap);
+        unsafe {
+            let base = ptr.as_ptr();
+            (
+                core::slice::from_raw_parts_mut(base.add(off1), len1),
+                core::slice::from_raw_parts_mut(base.add(off2), len2),
+            )
+        }
+    }
+
+    /// Copy as much of the body as fits into `buf`
+    ///
+    /// Returns the number of bytes copied.
+    pub fn copy_to(&self, buf: &mut [u8]) -> usize {
+        let (ptr, cap) = self.bbq.sto.ptr_len();
+        let hdr_sz = const { core::mem::size_of::<H>() };
+        let n = buf.len().min(self.len());
+        unsafe { read_split(ptr, cap, self.start + hdr_sz, &mut buf[..n]) };
+        n
+    }
+
+    /// Release the entire read grant
+    ///
+    /// It is not possible to partially release a framed read grant.
+    pub fn release(self) {
+        let hdrlen: usize = const { core::mem::size_of::<H>() };
+        let used = self.len() + hdrlen;
+        self.bbq.cor.release_wrapping(used);
+        self.bbq.not.wake_one_producer();
+        core::mem::forget(self);
+    }
+
+    /// Drop the grant WITHOUT releasing the message from the queue.
+    ///
+    /// The next call to read will observe the same packet again.
+    pub fn keep(self) {
+        // Default behavior is "keep"
+    }
+}
+
+impl<Q, H> Drop for WrappingGrantR<Q, H>
+where
+    Q: BbqHandle,
+    H: LenHeader,
+{
+    fn drop(&mut self) {
+        // Default behavior is "keep" - release zero bytes
+        self.bbq.cor.release_wrapping(0);
+    }
+}
+
+unsafe impl<Q, H> Send for WrappingGrantR<Q, H>
+where
+    Q: BbqHandle,
+    Q::Target: Send,
+    H: LenHeader + Send,
+{
+}
+
+#[cfg(all(test, target_has_atomic = "ptr"))]
+mod test {
+    use crate::{
+        queue::BBQueue,
+        traits::{coordination::cas::AtomicCoord, storage::Inline},
+    };
+
+    #[test]
+    fn wrapping_framed() {
+        use crate::traits::{mode::WrappingFramed, notifier::blocking::Blocking};
+
+        static BBQ: BBQueue<Inline<16>, AtomicCoord, Blocking, WrappingFramed> = BBQueue::new();
+        let (prod, cons) = BBQ.split().unwrap();
+
+        // Move to one byte before the end, so the next header is split
+        let wgr = prod.grant(13).unwrap();
+        wgr.commit(13);
+        cons.read().unwrap().release();
+
+        let mut wgr = prod.grant(6).unwrap();
+        let (a, b) = wgr.as_mut_slices();
+        assert_eq!((a.len(), b.len()), (6, 0));
+        assert_eq!(wgr.copy_from(&[1, 2, 3, 4, 5, 6, 7]), 6);
+        wgr.commit(6);
+
+        let rgr = cons.read().unwrap();
+        let mut buf = [0u8; 8];
+        assert_eq!(rgr.copy_to(&mut buf), 6);
+        assert_eq!(&buf[..6], &[1, 2, 3, 4, 5, 6]);
+        rgr.release();
+
+        // Frames of any size up to the free space fit, without skipping the
+        // tail of the buffer
+        let mut val = 0u8;
+        let mut expected = 0u8;
+        for i in 0..200usize {
+            let sz = (i * 7) % 13 + 1;
+            let mut wgr = prod.grant(sz as u16).unwrap();
+            let (a, b) = wgr.as_mut_slices();
+            for byte in a.iter_mut().chain(b.iter_mut()) {
+                *byte = val;
+                val = val.wrapping_add(1);
+            }
+            wgr.commit(sz as u16);
+
+            let rgr = cons.read().unwrap();
+            assert_eq!(rgr.len(), sz);
+            let (a, b) = rgr.as_slices();
+            for byte in a.iter().chain(b.iter()) {
+                assert_eq!(*byte, expected);
+                expected = expected.wrapping_add(1);
+            }
+            rgr.release();
+        }
+        assert!(cons.read().is_err());
+    }
+
+    #[test]
+    fn rewind_wrapping() {
+        use crate::traits::{mode::WrappingFramed, notifier::blocking::Blocking};
+
+        static BBQ: BBQueue<Inline<16>, AtomicCoord, Blocking, WrappingFramed> = BBQueue::new();
+        let (prod, cons) = BBQ.split().unwrap();
+
+        prod.grant(4).unwrap().commit(4);
+        cons.read().unwrap().release();
+
+        // The whole buffer is only available from the start, which the empty
+        // queue rewinds to
+        let mut wgr = prod.grant(14).unwrap();
+        assert_eq!(wgr.copy_from(&[7; 14]), 14);
+        wgr.commit(14);
+        let rgr = cons.read().unwrap();
+        let (a, b) = rgr.as_slices();
+        assert_eq!((a, b.len()), (&[7; 14][..], 0));
+    }
+}
@@ -2,11 +2,13 @@ use core::marker::PhantomData;
 
 use crate::{
     prod_cons::{
-        framed::{FramedConsumer, FramedProducer},
-        stream::{StreamConsumer, StreamProducer},
+        framed::{FramedConsumer, FramedProducer, LenHeader},
+        stream::{NotifyPolicy, StreamConsumer, StreamProducer},
+        wrapping::{WrappingConsumer, WrappingProducer},
     },
     traits::{
-        coordination::Coord,
+        coordination::{ClearError, Coord, HandleKind, Side, TakeError},
+        mode::{AnyMode, Framed, FramedMode, Stream, StreamMode, WrappingFramed, WrappingMode},
         notifier::Notifier,
         storage::{ConstStorage, Storage},
     },
@@ -16,95 +18,588 @@ use crate::{
 use crate::traits::bbqhdl::BbqHandle;
 
 /// A standard bbqueue
-pub struct BBQueue<S, C, N> {
+///
+/// The mode `M` may be used to restrict the queue to only stream or only framed
+/// handles at compile time, see the [`mode`](crate::traits::mode) module.
+///
+/// # Handles
+///
+/// Producers and consumers can be obtained in two ways:
+///
+/// * Tracked handles, from `split` and the `try_*` methods such as
+///   [`BBQueue::try_stream_producer`], take one side of the queue. They fail if
+///   that side has already been taken, or if the other side was taken by a
+///   different kind of handle, and give the side back when dropped.
+/// * Untracked handles, from methods such as [`BBQueue::stream_producer`], always
+///   succeed, and can be created in a `const` context, e.g. for statics. They
+///   bypass the tracking: the caller must make sure only one producer and one
+///   consumer are used at a time, and that they are of the same kind.
+pub struct BBQueue<S, C, N, M = AnyMode> {
     pub(crate) sto: S,
     pub(crate) cor: C,
     pub(crate) not: N,
+    pub(crate) mode: PhantomData<M>,
 }
 
-impl<S: Storage, C: Coord, N: Notifier> BBQueue<S, C, N> {
+impl<S: Storage, C: Coord, N: Notifier, M> BBQueue<S, C, N, M> {
     pub fn new_with_storage(sto: S) -> Self {
         Self {
             sto,
             cor: C::INIT,
             not: N::INIT,
+            mode: PhantomData,
         }
     }
 }
 
 /// A BBQueue wrapped in an Arc
 #[cfg(feature = "std")]
-pub struct ArcBBQueue<S, C, N>(pub(crate) std::sync::Arc<BBQueue<S, C, N>>);
+pub struct ArcBBQueue<S, C, N, M = AnyMode>(pub(crate) std::sync::Arc<BBQueue<S, C, N, M>>);
 
+/// The handle type used by producers and consumers of an [`ArcBBQueue`]
 #[cfg(feature = "std")]
-impl<S: Storage, C: Coord, N: Notifier> ArcBBQueue<S, C, N> {
+type ArcHandle<S, C, N, M> = std::sync::Arc<BBQueue<S, C, N, M>>;
+
+#[cfg(feature = "std")]
+impl<S: Storage, C: Coord, N: Notifier, M> ArcBBQueue<S, C, N, M> {
     pub fn new_with_storage(sto: S) -> Self {
         Self(std::sync::Arc::new(BBQueue::new_with_storage(sto)))
     }
 }
 
 #[allow(clippy::new_without_default)]
-impl<S: ConstStorage, C: Coord, N: Notifier> BBQueue<S, C, N> {
+impl<S: ConstStorage, C: Coord, N: Notifier, M> BBQueue<S, C, N, M> {
     pub const fn new() -> Self {
         Self {
             sto: S::INIT,
             cor: C::INIT,
             not: N::INIT,
+            mode: PhantomData,
         }
     }
 }
 
-impl<S: Storage, C: Coord, N: Notifier> BBQueue<S, C, N> {
-    pub const fn framed_producer(&self) -> FramedProducer<&'_ Self> {
+impl<S: Storage, C: Coord, N: Notifier, M> BBQueue<S, C, N, M> {
+    /// Discard all data in the queue
+    ///
+    /// Fails if a read or write grant is currently live. On success, a waiting
+    /// producer is woken, as the whole buffer is available again.
+    pub fn clear(&self) -> Result<(), ClearError> {
+        self.cor.reset()?;
+        self.not.wake_one_producer();
+        Ok(())
+    }
+}
+
+impl<S: Storage, C: Coord, N: Notifier, M: FramedMode> BBQueue<S, C, N, M> {
+    /// Obtain an untracked framed producer, see [Handles](BBQueue#handles)
+    pub const fn framed_producer(&self) -> FramedProducer<&'_ Self, M::Header> {
         FramedProducer {
             bbq: self,
             pd: PhantomData,
+            taken: false,
         }
     }
 
-    pub const fn framed_consumer(&self) -> FramedConsumer<&'_ Self> {
+    /// Obtain an untracked framed consumer, see [Handles](BBQueue#handles)
+    pub const fn framed_consumer(&self) -> FramedConsumer<&'_ Self, M::Header> {
         FramedConsumer {
             bbq: self,
             pd: PhantomData,
+            taken: false,
         }
     }
 
+    /// Take the framed producer of this queue
+    ///
+    /// Unlike [`BBQueue::framed_producer`], this fails if a tracked producer has
+    /// already been taken and not yet dropped, or if a tracked stream consumer
+    /// is live.
+    pub fn try_framed_producer(&self) -> Result<FramedProducer<&'_ Self, M::Header>, TakeError> {
+        self.cor
+            .take(Side::Producer, HandleKind::framed::<M::Header>())?;
+        let mut prod = self.framed_producer();
+        prod.taken = true;
+        Ok(prod)
+    }
+
+    /// Take the framed consumer of this queue
+    ///
+    /// Unlike [`BBQueue::framed_consumer`], this fails if a tracked consumer has
+    /// already been taken and not yet dropped, or if a tracked stream producer
+    /// is live.
+    pub fn try_framed_consumer(&self) -> Result<FramedConsumer<&'_ Self, M::Header>, TakeError> {
+        self.cor
+            .take(Side::Consumer, HandleKind::framed::<M::Header>())?;
+        let mut cons = self.framed_consumer();
+        cons.taken = true;
+        Ok(cons)
+    }
+
+    /// Take both the framed producer and consumer of this queue
+    ///
+    /// Fails if either side has already been taken. Both handles are given
+    /// back to the queue when they are dropped.
+    #[allow(clippy::type_complexity)]
+    pub fn split_framed(
+        &self,
+    ) -> Result<
+        (
+            FramedProducer<&'_ Self, M::Header>,
+            FramedConsumer<&'_ Self, M::Header>,
+        ),
+        TakeError,
+    > {
+        take_pair(&self.cor, HandleKind::framed::<M::Header>())?;
+        let mut prod = self.framed_producer();
+        let mut cons = self.framed_consumer();
+        prod.taken = true;
+        cons.taken = true;
+        Ok((prod, cons))
+    }
+}
+
+impl<S: Storage, C: Coord, N: Notifier, M: StreamMode> BBQueue<S, C, N, M> {
+    /// Obtain an untracked stream producer, see [Handles](BBQueue#handles)
     pub const fn stream_producer(&self) -> StreamProducer<&'_ Self> {
-        StreamProducer { bbq: self }
+        StreamProducer {
+            bbq: self,
+            policy: NotifyPolicy::Always,
+            taken: false,
+        }
     }
 
+    /// Obtain an untracked stream consumer, see [Handles](BBQueue#handles)
     pub const fn stream_consumer(&self) -> StreamConsumer<&'_ Self> {
-        StreamConsumer { bbq: self }
+        StreamConsumer {
+            bbq: self,
+            taken: false,
+        }
+    }
+
+    /// Take the stream producer of this queue
+    ///
+    /// Unlike [`BBQueue::stream_producer`], this fails if a tracked producer has
+    /// already been taken and not yet dropped, or if a tracked framed consumer
+    /// is live.
+    pub fn try_stream_producer(&self) -> Result<StreamProducer<&'_ Self>, TakeError> {
+        self.cor.take(Side::Producer, HandleKind::Stream)?;
+        let mut prod = self.stream_producer();
+        prod.taken = true;
+        Ok(prod)
+    }
+
+    /// Take the stream consumer of this queue
+    ///
+    /// Unlike [`BBQueue::stream_consumer`], this fails if a tracked consumer has
+    /// already been taken and not yet dropped, or if a tracked framed producer
+    /// is live.
+    pub fn try_stream_consumer(&self) -> Result<StreamConsumer<&'_ Self>, TakeError> {
+        self.cor.take(Side::Consumer, HandleKind::Stream)?;
+        let mut cons = self.stream_consumer();
+        cons.taken = true;
+        Ok(cons)
+    }
+
+    /// Take both the stream producer and consumer of this queue
+    ///
+    /// Fails if either side has already been taken. Both handles are given
+    /// back to the queue when they are dropped.
+    pub fn split_stream(
+        &self,
+    ) -> Result<(StreamProducer<&'_ Self>, StreamConsumer<&'_ Self>), TakeError> {
+        take_pair(&self.cor, HandleKind::Stream)?;
+        let mut prod = self.stream_producer();
+        let mut cons = self.stream_consumer();
+        prod.taken = true;
+        cons.taken = true;
+        Ok((prod, cons))
+    }
+}
+
+impl<S: Storage, C: Coord, N: Notifier, M: WrappingMode> BBQueue<S, C, N, M> {
+    /// Obtain an untracked wrapping framed producer, see [Handles](BBQueue#handles)
+    pub const fn wrapping_producer(&self) -> WrappingProducer<&'_ Self, M::Header> {
+        WrappingProducer {
+            bbq: self,
+            pd: PhantomData,
+            taken: false,
+        }
+    }
+
+    /// Obtain an untracked wrapping framed consumer, see [Handles](BBQueue#handles)
+    pub const fn wrapping_consumer(&self) -> WrappingConsumer<&'_ Self, M::Header> {
+        WrappingConsumer {
+            bbq: self,
+            pd: PhantomData,
+            taken: false,
+        }
+    }
+
+    /// Take the wrapping framed producer of this queue
+    ///
+    /// Unlike [`BBQueue::wrapping_producer`], this fails if a tracked producer
+    /// has already been taken and not yet dropped, or if a tracked consumer of
+    /// another kind is live.
+    pub fn try_wrapping_producer(
+        &self,
+    ) -> Result<WrappingProducer<&'_ Self, M::Header>, TakeError> {
+        self.cor
+            .take(Side::Producer, HandleKind::wrapping_framed::<M::Header>())?;
+        let mut prod = self.wrapping_producer();
+        prod.taken = true;
+        Ok(prod)
+    }
+
+    /// Take the wrapping framed consumer of this queue
+    ///
+    /// Unlike [`BBQueue::wrapping_consumer`], this fails if a tracked consumer
+    /// has already been taken and not yet dropped, or if a tracked producer of
+    /// another kind is live.
+    pub fn try_wrapping_consumer(
+        &self,
+    ) -> Result<WrappingConsumer<&'_ Self, M::Header>, TakeError> {
+        self.cor
+            .take(Side::Consumer, HandleKind::wrapping_framed::<M::Header>())?;
+        let mut cons = self.wrapping_consumer();
+        cons.taken = true;
+        Ok(cons)
+    }
+
+    /// Take both the wrapping framed producer and consumer of this queue
+    ///
+    /// Fails if either side has already been taken. Both handles are given
+    /// back to the queue when they are dropped.
+    #[allow(clippy::type_complexity)]
+    pub fn split_wrapping(
+        &self,
+    ) -> Result<
+        (
+            WrappingProducer<&'_ Self, M::Header>,
+            WrappingConsumer<&'_ Self, M::Header>,
+        ),
+        TakeError,
+    > {
+        take_pair(&self.cor, HandleKind::wrapping_framed::<M::Header>())?;
+        let mut prod = self.wrapping_producer();
+        let mut cons = self.wrapping_consumer();
+        prod.taken = true;
+        cons.taken = true;
+        Ok((prod, cons))
+    }
+}
+
+impl<S: Storage, C: Coord, N: Notifier> BBQueue<S, C, N, Stream> {
+    /// Take both the producer and consumer of this stream queue
+    ///
+    /// See [`BBQueue::split_stream`] for details.
+    pub fn split(&self) -> Result<(StreamProducer<&'_ Self>, StreamConsumer<&'_ Self>), TakeError> {
+        self.split_stream()
+    }
+}
+
+impl<S: Storage, C: Coord, N: Notifier, H: LenHeader> BBQueue<S, C, N, Framed<H>> {
+    /// Take both the producer and consumer of this framed queue
+    ///
+    /// See [`BBQueue::split_framed`] for details.
+    #[allow(clippy::type_complexity)]
+    pub fn split(
+        &self,
+    ) -> Result<(FramedProducer<&'_ Self, H>, FramedConsumer<&'_ Self, H>), TakeError> {
+        self.split_framed()
+    }
+}
+
+impl<S: Storage, C: Coord, N: Notifier, H: LenHeader> BBQueue<S, C, N, WrappingFramed<H>> {
+    /// Take both the producer and consumer of this wrapping framed queue
+    ///
+    /// See [`BBQueue::split_wrapping`] for details.
+    #[allow(clippy::type_complexity)]
+    pub fn split(
+        &self,
+    ) -> Result<(WrappingProducer<&'_ Self, H>, WrappingConsumer<&'_ Self, H>), TakeError> {
+        self.split_wrapping()
+    }
+}
+
+/// Take both sides of a queue, or neither
+fn take_pair<C: Coord>(cor: &C, kind: HandleKind) -> Result<(), TakeError> {
+    cor.take(Side::Producer, kind)?;
+    cor.take(Side::Consumer, kind).inspect_err(|_| {
+        cor.give_back(Side::Producer);
+    })
+}
+
+#[cfg(feature = "std")]
+impl<S: Storage, C: Coord, N: Notifier, M> ArcBBQueue<S, C, N, M> {
+    /// Discard all data in the queue
+    ///
+    /// See [`BBQueue::clear`] for details.
+    pub fn clear(&self) -> Result<(), ClearError> {
+        self.0.clear()
     }
 }
 
 #[cfg(feature = "std")]
-impl<S: Storage, C: Coord, N: Notifier> crate::queue::ArcBBQueue<S, C, N> {
-    pub fn framed_producer(&self) -> FramedProducer<std::sync::Arc<BBQueue<S, C, N>>> {
+impl<S: Storage, C: Coord, N: Notifier, M: FramedMode> ArcBBQueue<S, C, N, M> {
+    /// Obtain an untracked framed producer, see [Handles](BBQueue#handles)
+    pub fn framed_producer(&self) -> FramedProducer<ArcHandle<S, C, N, M>, M::Header> {
         FramedProducer {
             bbq: self.0.bbq_ref(),
             pd: PhantomData,
+            taken: false,
         }
     }
 
-    pub fn framed_consumer(&self) -> FramedConsumer<std::sync::Arc<BBQueue<S, C, N>>> {
+    /// Obtain an untracked framed consumer, see [Handles](BBQueue#handles)
+    pub fn framed_consumer(&self) -> FramedConsumer<ArcHandle<S, C, N, M>, M::Header> {
         FramedConsumer {
             bbq: self.0.bbq_ref(),
             pd: PhantomData,
+            taken: false,
         }
     }
 
-    pub fn stream_producer(&self) -> StreamProducer<std::sync::Arc<BBQueue<S, C, N>>> {
+    /// Take the framed producer of this queue
+    ///
+    /// See [`BBQueue::try_framed_producer`] for details.
+    #[allow(clippy::type_complexity)]
+    pub fn try_framed_producer(
+        &self,
+    ) -> Result<FramedProducer<ArcHandle<S, C, N, M>, M::Header>, TakeError> {
+        self.0
+            .cor
+            .take(Side::Producer, HandleKind::framed::<M::Header>())?;
+        let mut prod = self.framed_producer();
+        prod.taken = true;
+        Ok(prod)
+    }
+
+    /// Take the framed consumer of this queue
+    ///
+    /// See [`BBQueue::try_framed_consumer`] for details.
+    #[allow(clippy::type_complexity)]
+    pub fn try_framed_consumer(
+        &self,
+    ) -> Result<FramedConsumer<ArcHandle<S, C, N, M>, M::Header>, TakeError> {
+        self.0
+            .cor
+            .take(Side::Consumer, HandleKind::framed::<M::Header>())?;
+        let mut cons = self.framed_consumer();
+        cons.taken = true;
+        Ok(cons)
+    }
+
+    /// Take both the framed producer and consumer of this queue
+    ///
+    /// See [`BBQueue::split_framed`] for details.
+    #[allow(clippy::type_complexity)]
+    pub fn split_framed(
+        &self,
+    ) -> Result<
+        (
+            FramedProducer<ArcHandle<S, C, N, M>, M::Header>,
+            FramedConsumer<ArcHandle<S, C, N, M>, M::Header>,
+        ),
+        TakeError,
+    > {
+        take_pair(&self.0.cor, HandleKind::framed::<M::Header>())?;
+        let mut prod = self.framed_producer();
+        let mut cons = self.framed_consumer();
+        prod.taken = true;
+        cons.taken = true;
+        Ok((prod, cons))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: Storage, C: Coord, N: Notifier, M: StreamMode> ArcBBQueue<S, C, N, M> {
+    /// Obtain an untracked stream producer, see [Handles](BBQueue#handles)
+    pub fn stream_producer(&self) -> StreamProducer<ArcHandle<S, C, N, M>> {
         StreamProducer {
             bbq: self.0.bbq_ref(),
+            policy: NotifyPolicy::Always,
+            taken: false,
         }
     }
 
-    pub fn stream_consumer(&self) -> StreamConsumer<std::sync::Arc<BBQueue<S, C, N>>> {
+    /// Obtain an untracked stream consumer, see [Handles](BBQueue#handles)
+    pub fn stream_consumer(&self) -> StreamConsumer<ArcHandle<S, C, N, M>> {
         StreamConsumer {
             bbq: self.0.bbq_ref(),
+            taken: false,
+        }
+    }
+
+    /// Take the stream producer of this queue
+    ///
+    /// See [`BBQueue::try_stream_producer`] for details.
+    pub fn try_stream_producer(&self) -> Result<StreamProducer<ArcHandle<S, C, N, M>>, TakeError> {
+        self.0.cor.take(Side::Producer, HandleKind::Stream)?;
+        let mut prod = self.stream_producer();
+        prod.taken = true;
+        Ok(prod)
+    }
+
+    /// Take the stream consumer of this queue
+    ///
+    /// See [`BBQueue::try_stream_consumer`] for details.
+    pub fn try_stream_consumer(&self) -> Result<StreamConsumer<ArcHandle<S, C, N, M>>, TakeError> {
+        self.0.cor.take(Side::Consumer, HandleKind::Stream)?;
+        let mut cons = self.stream_consumer();
+        cons.taken = true;
+        Ok(cons)
+    }
+
+    /// Take both the stream producer and consumer of this queue
+    ///
+    /// See [`BBQueue::split_stream`] for details.
+    #[allow(clippy::type_complexity)]
+    pub fn split_stream(
+        &self,
+    ) -> Result<
+        (
+            StreamProducer<ArcHandle<S, C, N, M>>,
+            StreamConsumer<ArcHandle<S, C, N, M>>,
+        ),
+        TakeError,
+    > {
+        take_pair(&self.0.cor, HandleKind::Stream)?;
+        let mut prod = self.stream_producer();
+        let mut cons = self.stream_consumer();
+        prod.taken = true;
+        cons.taken = true;
+        Ok((prod, cons))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: Storage, C: Coord, N: Notifier, M: WrappingMode> ArcBBQueue<S, C, N, M> {
+    /// Obtain an untracked wrapping framed producer, see [Handles](BBQueue#handles)
+    pub fn wrapping_producer(&self) -> WrappingProducer<ArcHandle<S, C, N, M>, M::Header> {
+        WrappingProducer {
+            bbq: self.0.bbq_ref(),
+            pd: PhantomData,
+            taken: false,
         }
     }
+
+    /// Obtain an untracked wrapping framed consumer, see [Handles](BBQueue#handles)
+    pub fn wrapping_consumer(&self) -> WrappingConsumer<ArcHandle<S, C, N, M>, M::Header> {
+        WrappingConsumer {
+            bbq: self.0.bbq_ref(),
+            pd: PhantomData,
+            taken: false,
+        }
+    }
+
+    /// Take the wrapping framed producer of this queue
+    ///
+    /// See [`BBQueue::try_wrapping_producer`] for details.
+    #[allow(clippy::type_complexity)]
+    pub fn try_wrapping_producer(
+        &self,
+    ) -> Result<WrappingProducer<ArcHandle<S, C, N, M>, M::Header>, TakeError> {
+        self.0
+            .cor
+            .take(Side::Producer, HandleKind::wrapping_framed::<M::Header>())?;
+        let mut prod = self.wrapping_producer();
+        prod.taken = true;
+        Ok(prod)
+    }
+
+    /// Take the wrapping framed consumer of this queue
+    ///
+    /// See [`BBQueue::try_wrapping_consumer`] for details.
+    #[allow(clippy::type_complexity)]
+    pub fn try_wrapping_consumer(
+        &self,
+    ) -> Result<WrappingConsumer<ArcHandle<S, C, N, M>, M::Header>, TakeError> {
+        self.0
+            .cor
+            .take(Side::Consumer, HandleKind::wrapping_framed::<M::Header>())?;
+        let mut cons = self.wrapping_consumer();
+        cons.taken = true;
+        Ok(cons)
+    }
+
+    /// Take both the wrapping framed producer and consumer of this queue
+    ///
+    /// See [`BBQueue::split_wrapping`] for details.
+    #[allow(clippy::type_complexity)]
+    pub fn split_wrapping(
+        &self,
+    ) -> Result<
+        (
+            WrappingProducer<ArcHandle<S, C, N, M>, M::Header>,
+            WrappingConsumer<ArcHandle<S, C, N, M>, M::Header>,
+        ),
+        TakeError,
+    > {
+        take_pair(&self.0.cor, HandleKind::wrapping_framed::<M::Header>())?;
+        let mut prod = self.wrapping_producer();
+        let mut cons = self.wrapping_consumer();
+        prod.taken = true;
+        cons.taken = true;
+        Ok((prod, cons))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: Storage, C: Coord, N: Notifier> ArcBBQueue<S, C, N, Stream> {
+    /// Take both the producer and consumer of this stream queue
+    ///
+    /// See [`BBQueue::split_stream`] for details.
+    #[allow(clippy::type_complexity)]
+    pub fn split(
+        &self,
+    ) -> Result<
+        (
+            StreamProducer<ArcHandle<S, C, N, Stream>>,
+            StreamConsumer<ArcHandle<S, C, N, Stream>>,
+        ),
+        TakeError,
+    > {
+        self.split_stream()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: Storage, C: Coord, N: Notifier, H: LenHeader> ArcBBQueue<S, C, N, Framed<H>> {
+    /// Take both the producer and consumer of this framed queue
+    ///
+    /// See [`BBQueue::split_framed`] for details.
+    #[allow(clippy::type_complexity)]
+    pub fn split(
+        &self,
+    ) -> Result<
+        (
+            FramedProducer<ArcHandle<S, C, N, Framed<H>>, H>,
+            FramedConsumer<ArcHandle<S, C, N, Framed<H>>, H>,
+        ),
+        TakeError,
+    > {
+        self.split_framed()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: Storage, C: Coord, N: Notifier, H: LenHeader> ArcBBQueue<S, C, N, WrappingFramed<H>> {
+    /// Take both the producer and consumer of this wrapping framed queue
+    ///
+    /// See [`BBQueue::split_wrapping`] for details.
+    #[allow(clippy::type_complexity)]
+    pub fn split(
+        &self,
+    ) -> Result<
+        (
+            WrappingProducer<ArcHandle<S, C, N, WrappingFramed<H>>, H>,
+            WrappingConsumer<ArcHandle<S, C, N, WrappingFramed<H>>, H>,
+        ),
+        TakeError,
+    > {
+        self.split_wrapping()
+    }
 }
 
 #[cfg(test)]
@@ -133,4 +628,128 @@ mod test {
         }
         rgr.release();
     }
+
+    #[test]
+    fn clear() {
+        static QUEUE: Queue = BBQueue::new();
+        let prod = QUEUE.stream_producer();
+        let cons = QUEUE.stream_consumer();
+
+        prod.grant_exact(16).unwrap().commit(16);
+
+        // Not possible while a grant is live
+        let wgr = prod.grant_exact(4).unwrap();
+        assert_eq!(QUEUE.clear(), Err(ClearError::WriteGrantInProgress));
+        wgr.commit(4);
+        let rgr = cons.read().unwrap();
+        assert_eq!(prod.clear(), Err(ClearError::ReadGrantInProgress));
+        drop(rgr);
+
+        cons.clear().unwrap();
+        assert!(cons.read().is_err());
+        assert_eq!(prod.max_grant_len(), 4096);
+    }
+
+    #[test]
+    fn take_once() {
+        static QUEUE: Queue = BBQueue::new();
+
+        let prod = QUEUE.try_stream_producer().unwrap();
+        assert_eq!(
+            QUEUE.try_stream_producer().err(),
+            Some(TakeError::AlreadyTaken)
+        );
+        // Mixing stream and framed handles is caught
+        assert_eq!(
+            QUEUE.try_framed_consumer().err(),
+            Some(TakeError::KindMismatch)
+        );
+        assert_eq!(QUEUE.split_stream().err(), Some(TakeError::AlreadyTaken));
+
+        // Handles are given back on drop
+        drop(prod);
+        let (prod, cons) = QUEUE.split_stream().unwrap();
+        assert!(QUEUE.try_stream_consumer().is_err());
+        drop((prod, cons));
+        let (prod, cons) = QUEUE.split_framed().unwrap();
+        drop((prod, cons));
+
+        // Wrapping framed handles can be taken one side at a time as well
+        let prod = QUEUE.try_wrapping_producer().unwrap();
+        assert_eq!(
+            QUEUE.try_wrapping_producer().err(),
+            Some(TakeError::AlreadyTaken)
+        );
+        assert_eq!(
+            QUEUE.try_framed_consumer().err(),
+            Some(TakeError::KindMismatch)
+        );
+        let _cons = QUEUE.try_wrapping_consumer().unwrap();
+        drop(prod);
+    }
+
+    #[test]
+    fn typed_modes() {
+        static STREAM: BBQueue<Inline<64>, AtomicCoord, Blocking, Stream> = BBQueue::new();
+        static FRAMED: BBQueue<Inline<64>, AtomicCoord, Blocking, Framed<usize>> = BBQueue::new();
+
+        let (prod, cons) = STREAM.split().unwrap();
+        prod.grant_exact(4).unwrap().commit(4);
+        assert_eq!(cons.read().unwrap().len(), 4);
+
+        // The header of framed handles is fixed by the mode
+        let (prod, cons) = FRAMED.split().unwrap();
+        let prod: FramedProducer<_, usize> = prod;
+        prod.grant(8).unwrap().commit(8);
+        assert_eq!(cons.read().unwrap().len(), 8);
+        assert!(FRAMED.try_framed_producer().is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[tokio::test]
+    async fn arc_close_on_drop() {
+        use crate::traits::{
+            coordination::{ReadGrantError, WriteGrantError},
+            notifier::maitake::MaiNotSpsc,
+        };
+        use core::{ops::Deref, time::Duration};
+
+        let bbq: ArcBBQueue<Inline<64>, AtomicCoord, MaiNotSpsc> =
+            ArcBBQueue::new_with_storage(Inline::new());
+        let (prod, cons) = bbq.split_framed().unwrap();
+
+        // Untracked handles don't own a side of the queue, so they never close it
+        assert!(bbq.stream_producer().is_empty());
+        assert!(!prod.is_closed());
+
+        let rxfut = tokio::task::spawn(async move {
+            let rgr = cons.wait_read().await.unwrap();
+            assert_eq!(rgr.deref(), &[1, 2, 3]);
+            rgr.release();
+            // The producer is gone, and the queue is drained
+            assert_eq!(cons.wait_read().await.err(), Some(ReadGrantError::Closed));
+        });
+
+        let txfut = tokio::task::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let mut wgr = prod.grant(3).unwrap();
+            wgr.copy_from_slice(&[1, 2, 3]);
+            wgr.commit(3);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            drop(prod);
+        });
+
+        tokio::time::timeout(Duration::from_secs(1), rxfut)
+            .await
+            .unwrap()
+            .unwrap();
+        txfut.await.unwrap();
+
+        // The consumer is gone too, so writers are turned away
+        let prod = bbq.stream_producer();
+        assert_eq!(
+            prod.wait_grant_exact(1).await.err(),
+            Some(WriteGrantError::Closed)
+        );
+    }
 }
@@ -0,0 +1,286 @@
+//! Waiting on several queues at once
+//!
+//! A single task often services several consumers, each with a different
+//! storage size, and therefore a different type. [`select_read`](crate::select::select_read)
+//! waits for the first of them that becomes readable, and returns which one it
+//! was, along with its read grant:
+//!
+//! * A tuple of up to four consumers of any kind returns one of
+//!   [`Selected2`](crate::select::Selected2), [`Selected3`](crate::select::Selected3)
+//!   or [`Selected4`](crate::select::Selected4), with a variant per queue.
+//! * An array of consumers of the same type returns the index of the queue.
+//!
+//! ```rust
+//! # #[cfg(target_has_atomic = "ptr")]
+//! # async fn example() {
+//! use bbq2::{
+//!     prod_cons::framed::FramedConsumer,
+//!     queue::BBQueue,
+//!     select::{Selected2, select_read},
+//!     traits::{coordination::cas::AtomicCoord, notifier::maitake::MaiNotSpsc, storage::Inline},
+//! };
+//!
+//! static CMDS: BBQueue<Inline<64>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+//! static UART: BBQueue<Inline<256>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+//!
+//! let cmds: FramedConsumer<_> = CMDS.framed_consumer();
+//! let uart = UART.stream_consumer();
+//! match select_read((&cmds, &uart)).await {
+//!     Selected2::First(Ok(frame)) => frame.release(),
+//!     Selected2::Second(Ok(bytes)) => {
+//!         let len = bytes.len();
+//!         bytes.release(len)
+//!     }
+//!     Selected2::First(Err(_)) | Selected2::Second(Err(_)) => {}
+//! }
+//! # }
+//! ```
+//!
+//! Queues are checked in order, so when several are readable at once, the
+//! earlier ones take priority. Errors, such as a queue being closed, are returned
+//! like grants, so a closed queue should be removed from the set. Dropping the
+//! future returned by [`select_read`](crate::select::select_read) never loses
+//! any data.
+
+use core::{
+    future::{Future, poll_fn},
+    pin::pin,
+    task::Poll,
+};
+
+use crate::{
+    prod_cons::{
+        fragmented::{ChunkGrantR, FragmentedConsumer},
+        framed::{FramedConsumer, FramedGrantR, LenHeader},
+        stream::{StreamConsumer, StreamGrantR},
+        wrapping::{WrappingConsumer, WrappingGrantR},
+    },
+    traits::{bbqhdl::BbqHandle, coordination::ReadGrantError, notifier::AsyncNotifier},
+};
+
+/// A consumer that can be waited on with [`select_read`]
+pub trait SelectRead {
+    /// The read grant obtained from the consumer
+    type Grant;
+
+    /// Wait for a read grant, see e.g. [`StreamConsumer::wait_read`]
+    fn wait_read(&self) -> impl Future<Output = Result<Self::Grant, ReadGrantError>>;
+}
+
+impl<T: SelectRead + ?Sized> SelectRead for &T {
+    type Grant = T::Grant;
+
+    fn wait_read(&self) -> impl Future<Output = Result<Self::Grant, ReadGrantError>> {
+        T::wait_read(self)
+    }
+}
+
+impl<Q> SelectRead for StreamConsumer<Q>
+where
+    Q: BbqHandle,
+    Q::Notifier: AsyncNotifier,
+{
+    type Grant = StreamGrantR<Q>;
+
+    fn wait_read(&self) -> impl Future<Output = Result<Self::Grant, ReadGrantError>> {
+        StreamConsumer::wait_read(self)
+    }
+}
+
+impl<Q, H> SelectRead for FramedConsumer<Q, H>
+where
+    Q: BbqHandle,
+    Q::Notifier: AsyncNotifier,
+    H: LenHeader,
+{
+    type Grant = FramedGrantR<Q, H>;
+
+    fn wait_read(&self) -> impl Future<Output = Result<Self::Grant, ReadGrantError>> {
+        FramedConsumer::wait_read(self)
+    }
+}
+
+impl<Q, H> SelectRead for WrappingConsumer<Q, H>
+where
+    Q: BbqHandle,
+    Q::Notifier: AsyncNotifier,
+    H: LenHeader,
+{
+    type Grant = WrappingGrantR<Q, H>;
+
+    fn wait_read(&self) -> impl Future<Output = Result<Self::Grant, ReadGrantError>> {
+        WrappingConsumer::wait_read(self)
+    }
+}
+
+impl<Q, H> SelectRead for FragmentedConsumer<Q, H>
+where
+    Q: BbqHandle,
+    Q::Notifier: AsyncNotifier,
+    H: LenHeader,
+{
+    type Grant = ChunkGrantR<Q, H>;
+
+    fn wait_read(&self) -> impl Future<Output = Result<Self::Grant, ReadGrantError>> {
+        FragmentedConsumer::wait_read_chunk(self)
+    }
+}
+
+/// A set of consumers that can be waited on with [`select_read`]
+///
+/// This is implemented for tuples of two to four [`SelectRead`] consumers, and
+/// for arrays of them.
+pub trait ReadSet {
+    /// Which consumer became readable, along with its grant
+    type Output;
+
+    /// Wait for the first consumer of the set that becomes readable
+    fn select_read(&self) -> impl Future<Output = Self::Output>;
+}
+
+impl<S: ReadSet + ?Sized> ReadSet for &S {
+    type Output = S::Output;
+
+    fn select_read(&self) -> impl Future<Output = Self::Output> {
+        S::select_read(self)
+    }
+}
+
+/// Wait for the first consumer of `set` that becomes readable
+///
+/// See the [module documentation](crate::select) for details.
+pub async fn select_read<S: ReadSet>(set: S) -> S::Output {
+    set.select_read().await
+}
+
+macro_rules! impl_read_set {
+    ($name:ident: $($ty:ident $fut:ident $idx:tt $var:ident),+) => {
+        /// Which consumer of a tuple became readable, along with its grant
+        #[derive(Debug)]
+        pub enum $name<$($ty),+> {
+            $(
+                /// The consumer at this position of the tuple became readable
+                $var($ty),
+            )+
+        }
+
+        impl<$($ty: SelectRead),+> ReadSet for ($($ty,)+) {
+            type Output = $name<$(Result<$ty::Grant, ReadGrantError>),+>;
+
+            async fn select_read(&self) -> Self::Output {
+                $(let mut $fut = pin!(self.$idx.wait_read());)+
+                poll_fn(|cx| {
+                    $(
+                        if let Poll::Ready(res) = $fut.as_mut().poll(cx) {
+                            return Poll::Ready($name::$var(res));
+                        }
+                    )+
+                    Poll::Pending
+                })
+                .await
+            }
+        }
+    };
+}
+
+impl_read_set!(Selected2: A fa 0 First, B fb 1 Second);
+impl_read_set!(Selected3: A fa 0 First, B fb 1 Second, C fc 2 Third);
+impl_read_set!(Selected4: A fa 0 First, B fb 1 Second, C fc 2 Third, D fd 3 Fourth);
+
+impl<T: SelectRead, const N: usize> ReadSet for [T; N] {
+    type Output = (usize, Result<T::Grant, ReadGrantError>);
+
+    async fn select_read(&self) -> Self::Output {
+        let mut futs = pin!(core::array::from_fn::<_, N, _>(|i| self[i].wait_read()));
+        poll_fn(|cx| {
+            for i in 0..N {
+                // SAFETY: The futures are never moved out of the pinned array
+                let fut = unsafe { futs.as_mut().map_unchecked_mut(|futs| &mut futs[i]) };
+                if let Poll::Ready(res) = fut.poll(cx) {
+                    return Poll::Ready((i, res));
+                }
+            }
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+#[cfg(all(test, target_has_atomic = "ptr"))]
+mod test {
+    use core::{ops::Deref, time::Duration};
+
+    use crate::{
+        queue::BBQueue,
+        traits::{coordination::cas::AtomicCoord, notifier::maitake::MaiNotSpsc, storage::Inline},
+    };
+
+    #[tokio::test]
+    async fn select_reads() {
+        use crate::{
+            prod_cons::framed::{FramedConsumer, FramedProducer},
+            select::{Selected2, select_read},
+            traits::coordination::ReadGrantError,
+        };
+
+        static FRAMES: BBQueue<Inline<16>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        static BYTES: BBQueue<Inline<32>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        let fcons: FramedConsumer<_> = FRAMES.framed_consumer();
+        let fprod: FramedProducer<_> = FRAMES.framed_producer();
+        let scons = BYTES.stream_consumer();
+        let sprod = BYTES.stream_producer();
+
+        // Wakes on whichever queue is written
+        let txfut = tokio::task::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            sprod.push(&[1, 2, 3]);
+            sprod
+        });
+        match select_read((&fcons, &scons)).await {
+            Selected2::Second(Ok(rgr)) => {
+                assert_eq!(rgr.deref(), &[1, 2, 3]);
+                rgr.release(3);
+            }
+            _ => panic!("wrong queue selected"),
+        }
+        let sprod = txfut.await.unwrap();
+
+        // Earlier queues take priority, and the other one is left as is
+        fprod.push_frame(&[4]).unwrap();
+        sprod.push(&[5]);
+        match select_read((&fcons, &scons)).await {
+            Selected2::First(Ok(rgr)) => {
+                assert_eq!(rgr.deref(), &[4]);
+                rgr.release();
+            }
+            _ => panic!("wrong queue selected"),
+        }
+        match select_read((&fcons, &scons)).await {
+            Selected2::Second(Ok(rgr)) => {
+                assert_eq!(rgr.deref(), &[5]);
+                rgr.release(1);
+            }
+            _ => panic!("wrong queue selected"),
+        }
+
+        // Arrays of the same type return the index
+        static A: BBQueue<Inline<16>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        static B: BBQueue<Inline<16>, AtomicCoord, MaiNotSpsc> = BBQueue::new();
+        let cons = [A.stream_consumer(), B.stream_consumer()];
+        let prod = B.stream_producer();
+        let txfut = tokio::task::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            prod.push(&[6]);
+            prod.close();
+        });
+        let (idx, rgr) = select_read(&cons).await;
+        assert_eq!(idx, 1);
+        rgr.unwrap().release(1);
+        txfut.await.unwrap();
+
+        // Closed queues are reported like grants
+        let (idx, res) = select_read(&cons).await;
+        assert_eq!(idx, 1);
+        assert_eq!(res.err(), Some(ReadGrantError::Closed));
+    }
+}
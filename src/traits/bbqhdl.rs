@@ -20,59 +20,125 @@ use core::{marker::PhantomData, ops::Deref};
 use crate::{
     prod_cons::{
         framed::{FramedConsumer, FramedProducer, LenHeader},
-        stream::{StreamConsumer, StreamProducer},
+        stream::{NotifyPolicy, StreamConsumer, StreamProducer},
+        wrapping::{WrappingConsumer, WrappingProducer},
     },
     queue::BBQueue,
 };
 
-use super::{coordination::Coord, notifier::Notifier, storage::Storage};
+use super::{
+    coordination::Coord,
+    mode::{AllowsHeader, AllowsWrapping, StreamMode},
+    notifier::Notifier,
+    storage::Storage,
+};
 
 /// The "Access" trait
 pub trait BbqHandle: Sized {
     /// How we reference our BBQueue.
-    type Target: Deref<Target = BBQueue<Self::Storage, Self::Coord, Self::Notifier>> + Clone;
+    type Target: Deref<Target = BBQueue<Self::Storage, Self::Coord, Self::Notifier, Self::Mode>>
+        + Clone;
     /// How the DATA of our BBQueue is stored
     type Storage: Storage;
     /// How the producers/consumers of this BBQueue coordinate
     type Coord: Coord;
     /// How we notify the producer/consumers of this BBQueue
     type Notifier: Notifier;
+    /// Which kinds of producers/consumers this BBQueue allows
+    type Mode;
+
+    /// Whether taken producers and consumers close the queue when they are dropped
+    ///
+    /// This is `false` for borrowed queues, where handles are cheap to create
+    /// on demand or may live in statics, and `true` for `Arc` queues, where each
+    /// handle owns its share of the queue. Only handles that were taken, e.g.
+    /// with `try_stream_producer` or `split`, close the queue, as any number of
+    /// untracked handles may exist at once.
+    const CLOSE_ON_DROP: bool;
 
     // Obtain a reference to
     fn bbq_ref(&self) -> Self::Target;
 
-    fn stream_producer(&self) -> StreamProducer<Self> {
+    /// Obtain an untracked stream producer, see [Handles](BBQueue#handles)
+    fn stream_producer(&self) -> StreamProducer<Self>
+    where
+        Self::Mode: StreamMode,
+    {
         StreamProducer {
             bbq: self.bbq_ref(),
+            policy: NotifyPolicy::Always,
+            taken: false,
         }
     }
 
-    fn stream_consumer(&self) -> StreamConsumer<Self> {
+    /// Obtain an untracked stream consumer, see [Handles](BBQueue#handles)
+    fn stream_consumer(&self) -> StreamConsumer<Self>
+    where
+        Self::Mode: StreamMode,
+    {
         StreamConsumer {
             bbq: self.bbq_ref(),
+            taken: false,
         }
     }
 
-    fn framed_producer<H: LenHeader>(&self) -> FramedProducer<Self, H> {
+    /// Obtain an untracked framed producer, see [Handles](BBQueue#handles)
+    fn framed_producer<H: LenHeader>(&self) -> FramedProducer<Self, H>
+    where
+        Self::Mode: AllowsHeader<H>,
+    {
         FramedProducer {
             bbq: self.bbq_ref(),
             pd: PhantomData,
+            taken: false,
         }
     }
 
-    fn framed_consumer<H: LenHeader>(&self) -> FramedConsumer<Self, H> {
+    /// Obtain an untracked framed consumer, see [Handles](BBQueue#handles)
+    fn framed_consumer<H: LenHeader>(&self) -> FramedConsumer<Self, H>
+    where
+        Self::Mode: AllowsHeader<H>,
+    {
         FramedConsumer {
             bbq: self.bbq_ref(),
             pd: PhantomData,
+            taken: false,
+        }
+    }
+
+    /// Obtain an untracked wrapping framed producer, see [Handles](BBQueue#handles)
+    fn wrapping_producer<H: LenHeader>(&self) -> WrappingProducer<Self, H>
+    where
+        Self::Mode: AllowsWrapping<H>,
+    {
+        WrappingProducer {
+            bbq: self.bbq_ref(),
+            pd: PhantomData,
+            taken: false,
+        }
+    }
+
+    /// Obtain an untracked wrapping framed consumer, see [Handles](BBQueue#handles)
+    fn wrapping_consumer<H: LenHeader>(&self) -> WrappingConsumer<Self, H>
+    where
+        Self::Mode: AllowsWrapping<H>,
+    {
+        WrappingConsumer {
+            bbq: self.bbq_ref(),
+            pd: PhantomData,
+            taken: false,
         }
     }
 }
 
-impl<S: Storage, C: Coord, N: Notifier> BbqHandle for &'_ BBQueue<S, C, N> {
+impl<S: Storage, C: Coord, N: Notifier, M> BbqHandle for &'_ BBQueue<S, C, N, M> {
     type Target = Self;
     type Storage = S;
     type Coord = C;
     type Notifier = N;
+    type Mode = M;
+
+    const CLOSE_ON_DROP: bool = false;
 
     #[inline(always)]
     fn bbq_ref(&self) -> Self::Target {
@@ -81,11 +147,14 @@ impl<S: Storage, C: Coord, N: Notifier> BbqHandle for &'_ BBQueue<S, C, N> {
 }
 
 #[cfg(feature = "std")]
-impl<S: Storage, C: Coord, N: Notifier> BbqHandle for std::sync::Arc<BBQueue<S, C, N>> {
+impl<S: Storage, C: Coord, N: Notifier, M> BbqHandle for std::sync::Arc<BBQueue<S, C, N, M>> {
     type Target = Self;
     type Storage = S;
     type Coord = C;
     type Notifier = N;
+    type Mode = M;
+
+    const CLOSE_ON_DROP: bool = true;
 
     #[inline(always)]
     fn bbq_ref(&self) -> Self::Target {
@@ -1,6 +1,9 @@
 //! Lock-free coordination based on Compare and Swap atomics
 
-use super::{Coord, ReadGrantError, WriteGrantError};
+use super::{
+    ClearError, Coord, HandleKind, ReadGrantError, Side, Snapshot, TakeError, WriteGrantError,
+    give_back_mask, take_bits,
+};
 use core::{
     cmp::min,
     sync::atomic::{AtomicBool, AtomicUsize, Ordering},
@@ -32,6 +35,12 @@ pub struct AtomicCoord {
 
     /// Is there an active write grant?
     write_in_progress: AtomicBool,
+
+    /// Has the queue been closed by either side?
+    closed: AtomicBool,
+
+    /// Which kind of handle has been taken for each side, if any
+    taken: AtomicUsize,
 }
 
 impl AtomicCoord {
@@ -43,10 +52,43 @@ impl AtomicCoord {
             reserve: AtomicUsize::new(0),
             read_in_progress: AtomicBool::new(false),
             write_in_progress: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            taken: AtomicUsize::new(0),
         }
     }
 }
 
+impl AtomicCoord {
+    /// Move the indices of an empty queue back to the start of the buffer
+    ///
+    /// Called by the producer while holding the write grant, with the `write`
+    /// and `read` it observed, so that a grant that only fits at the start of
+    /// the buffer does not have to wait for the consumer to wrap around. Fails
+    /// if the queue is not empty, or if the consumer is looking at the indices.
+    fn rewind(&self, write: usize, read: usize) -> bool {
+        let last = self.last.load(Ordering::Acquire);
+        if !(Snapshot { write, read, last }).is_empty() {
+            return false;
+        }
+        // Keep the consumer away from the indices while moving `read`
+        if self.read_in_progress.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        // Without a read grant, the consumer may only have wrapped `read` to
+        // the start, so the queue is still empty
+        let read = self.read.load(Ordering::Acquire);
+
+        // Keep the queue looking empty to anyone taking a snapshot meanwhile,
+        // by moving `write` to the start first, as if it had wrapped around
+        self.last.store(read, Ordering::Release);
+        self.write.store(0, Ordering::Release);
+        self.read.store(0, Ordering::Release);
+
+        self.read_in_progress.store(false, Ordering::Release);
+        true
+    }
+}
+
 impl Default for AtomicCoord {
     fn default() -> Self {
         Self::new()
@@ -57,12 +99,71 @@ unsafe impl Coord for AtomicCoord {
     #[allow(clippy::declare_interior_mutable_const)]
     const INIT: Self = Self::new();
 
-    fn reset(&self) {
+    fn reset(&self) -> Result<(), ClearError> {
+        // Take both grants, so nobody else touches the indices while we reset them
+        if self.write_in_progress.swap(true, Ordering::AcqRel) {
+            return Err(ClearError::WriteGrantInProgress);
+        }
+        if self.read_in_progress.swap(true, Ordering::AcqRel) {
+            self.write_in_progress.store(false, Ordering::Release);
+            return Err(ClearError::ReadGrantInProgress);
+        }
+
         // Re-initialize the buffer (not totally needed, but nice to do)
         self.write.store(0, Ordering::Release);
         self.read.store(0, Ordering::Release);
         self.reserve.store(0, Ordering::Release);
         self.last.store(0, Ordering::Release);
+
+        self.read_in_progress.store(false, Ordering::Release);
+        self.write_in_progress.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    fn take(&self, side: Side, kind: HandleKind) -> Result<(), TakeError> {
+        let mut taken = self.taken.load(Ordering::Acquire);
+        loop {
+            let new = take_bits(taken, side, kind)?;
+            match self
+                .taken
+                .compare_exchange_weak(taken, new, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return Ok(()),
+                Err(actual) => taken = actual,
+            }
+        }
+    }
+
+    fn give_back(&self, side: Side) {
+        self.taken.fetch_and(give_back_mask(side), Ordering::AcqRel);
+    }
+
+    fn close(&self) -> bool {
+        !self.closed.swap(true, Ordering::AcqRel)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        // `last` is always updated before `write` by the producer. Loading
+        // `write` first means we never observe a `write` newer than `last`.
+        // Either side may move its index while we are loading the others, so
+        // only accept the snapshot once neither `write` nor `read` has moved.
+        let mut write = self.write.load(Ordering::Acquire);
+        let mut read = self.read.load(Ordering::Acquire);
+        loop {
+            let last = self.last.load(Ordering::Acquire);
+            let (new_write, new_read) = (
+                self.write.load(Ordering::Acquire),
+                self.read.load(Ordering::Acquire),
+            );
+            if (new_write, new_read) == (write, read) {
+                return Snapshot { write, read, last };
+            }
+            (write, read) = (new_write, new_read);
+        }
     }
 
     fn grant_max_remaining(
@@ -74,6 +175,11 @@ unsafe impl Coord for AtomicCoord {
             return Err(WriteGrantError::GrantInProgress);
         }
 
+        if self.closed.load(Ordering::Acquire) {
+            self.write_in_progress.store(false, Ordering::Release);
+            return Err(WriteGrantError::Closed);
+        }
+
         // Writer component. Must never write to `read`,
         // be careful writing to `load`
         let write = self.write.load(Ordering::Acquire);
@@ -128,6 +234,16 @@ unsafe impl Coord for AtomicCoord {
             return Err(WriteGrantError::GrantInProgress);
         }
 
+        if self.closed.load(Ordering::Acquire) {
+            self.write_in_progress.store(false, Ordering::Release);
+            return Err(WriteGrantError::Closed);
+        }
+
+        if sz > capacity {
+            self.write_in_progress.store(false, Ordering::Release);
+            return Err(WriteGrantError::TooLarge);
+        }
+
         // Writer component. Must never write to `read`,
         // be careful writing to `load`
         let write = self.write.load(Ordering::Acquire);
@@ -139,6 +255,9 @@ unsafe impl Coord for AtomicCoord {
             if (write + sz) < read {
                 // Inverted, room is still available
                 write
+            } else if self.rewind(write, read) {
+                // Empty, as the reader has not caught up with the inversion yet
+                0
             } else {
                 // Inverted, no room is available
                 self.write_in_progress.store(false, Ordering::Release);
@@ -158,10 +277,13 @@ unsafe impl Coord for AtomicCoord {
                 if sz < read {
                     // Invertible situation
                     0
+                } else if self.rewind(write, read) {
+                    // Empty, so the whole buffer is available from the start
+                    0
                 } else {
                     // Not invertible, no space
                     self.write_in_progress.store(false, Ordering::Release);
-                    return Err(WriteGrantError::InsufficientSize);
+                    return Err(WriteGrantError::WouldWrap);
                 }
             }
         };
@@ -205,7 +327,12 @@ unsafe impl Coord for AtomicCoord {
 
         if sz == 0 {
             self.read_in_progress.store(false, Ordering::Release);
-            return Err(ReadGrantError::Empty);
+            // Only report closed once all data has been drained
+            return Err(if self.closed.load(Ordering::Acquire) {
+                ReadGrantError::Closed
+            } else {
+                ReadGrantError::Empty
+            });
         }
 
         Ok((read, sz))
@@ -261,6 +388,37 @@ unsafe impl Coord for AtomicCoord {
         self.write_in_progress.store(false, Ordering::Release);
     }
 
+    fn grant_extend(
+        &self,
+        capacity: usize,
+        grant_len: usize,
+        additional: usize,
+    ) -> Result<(), WriteGrantError> {
+        // Writer component. Only viewed by this task
+        let reserve = self.reserve.load(Ordering::Acquire);
+        let start = reserve - grant_len;
+        let read = self.read.load(Ordering::Acquire);
+        let new_end = reserve
+            .checked_add(additional)
+            .ok_or(WriteGrantError::InsufficientSize)?;
+
+        // If the grant is before the read position, it must never catch up
+        // with read. Otherwise, it may extend up to the end of the buffer.
+        // `read` may move backwards to zero while we are checking, but that
+        // only frees more space.
+        let fits = if start < read {
+            new_end < read
+        } else {
+            new_end <= capacity
+        };
+        if !fits {
+            return Err(WriteGrantError::InsufficientSize);
+        }
+
+        self.reserve.store(new_end, Ordering::Release);
+        Ok(())
+    }
+
     fn release_inner(&self, used: usize) {
         // If there is no grant in progress, return early. This
         // generally means we are dropping the grant within a
@@ -277,4 +435,172 @@ unsafe impl Coord for AtomicCoord {
 
         self.read_in_progress.store(false, Ordering::Release);
     }
+
+    fn read_refresh(&self) -> usize {
+        // Reader component. `read` is not moved while a grant is live
+        let write = self.write.load(Ordering::Acquire);
+        let last = self.last.load(Ordering::Acquire);
+        let read = self.read.load(Ordering::Acquire);
+
+        if write < read {
+            // Inverted, only believe last
+            last
+        } else {
+            // Not inverted, only believe write
+            write
+        }
+        .saturating_sub(read)
+    }
+
+    fn grant_wrapping(&self, capacity: usize, sz: usize) -> Result<usize, WriteGrantError> {
+        if self.write_in_progress.swap(true, Ordering::AcqRel) {
+            return Err(WriteGrantError::GrantInProgress);
+        }
+
+        if self.closed.load(Ordering::Acquire) {
+            self.write_in_progress.store(false, Ordering::Release);
+            return Err(WriteGrantError::Closed);
+        }
+
+        if sz > capacity {
+            self.write_in_progress.store(false, Ordering::Release);
+            return Err(WriteGrantError::TooLarge);
+        }
+
+        // Writer component. Must never write to `read`,
+        // be careful writing to `load`
+        let write = self.write.load(Ordering::Acquire);
+        let read = self.read.load(Ordering::Acquire);
+        let max = capacity;
+
+        let start = if write < read {
+            if (write + sz) < read {
+                // Inverted, room is still available
+                write
+            } else if self.rewind(write, read) {
+                // Empty, as the reader has not caught up with the inversion yet
+                0
+            } else {
+                // Inverted, no room is available
+                self.write_in_progress.store(false, Ordering::Release);
+                return Err(WriteGrantError::InsufficientSize);
+            }
+        } else if write + sz <= max {
+            // Non inverted condition
+            write
+        } else if sz - (max - write) < read {
+            // Continue at the start of the buffer. As with other inversions,
+            // write must never catch up with read.
+            if write == max { 0 } else { write }
+        } else if sz == max && self.rewind(write, read) {
+            // Empty, so the whole buffer is available from the start
+            0
+        } else if sz == max {
+            // The full buffer is only available when write is at the start
+            self.write_in_progress.store(false, Ordering::Release);
+            return Err(WriteGrantError::WouldWrap);
+        } else {
+            self.write_in_progress.store(false, Ordering::Release);
+            return Err(WriteGrantError::InsufficientSize);
+        };
+
+        // Safe write, only viewed by this task
+        self.reserve.store(start + sz, Ordering::Release);
+
+        Ok(start)
+    }
+
+    fn commit_wrapping(&self, capacity: usize, start: usize, used: usize) {
+        // If there is no grant in progress, return early. This
+        // generally means we are dropping the grant within a
+        // wrapper structure
+        if !self.write_in_progress.load(Ordering::Acquire) {
+            return;
+        }
+
+        let write = self.write.load(Ordering::Acquire);
+        let last = self.last.load(Ordering::Acquire);
+        let max = capacity;
+        let end = start + used;
+
+        let new_write = if end > max {
+            // The committed data continues at the start of the buffer, so the
+            // whole tail is readable
+            self.last.store(max, Ordering::Release);
+            end - max
+        } else {
+            // Same as a contiguous commit, see `commit_inner`
+            if (end < write) && (write != max) {
+                self.last.store(write, Ordering::Release);
+            } else if end > last {
+                self.last.store(max, Ordering::Release);
+            }
+            end
+        };
+
+        // Write must be updated AFTER last, otherwise read could think it was
+        // time to invert early!
+        self.write.store(new_write, Ordering::Release);
+
+        // Allow subsequent grants
+        self.write_in_progress.store(false, Ordering::Release);
+    }
+
+    fn read_wrapping(&self) -> Result<(usize, usize, usize), ReadGrantError> {
+        if self.read_in_progress.swap(true, Ordering::AcqRel) {
+            return Err(ReadGrantError::GrantInProgress);
+        }
+
+        let write = self.write.load(Ordering::Acquire);
+        let last = self.last.load(Ordering::Acquire);
+        let mut read = self.read.load(Ordering::Acquire);
+
+        // Resolve the inverted case or end of read, see `read`
+        if (read == last) && (write < read) {
+            read = 0;
+            self.read.store(0, Ordering::Release);
+        }
+
+        let (first, second) = if write < read {
+            // Inverted, data remains in [read, last) and [0, write)
+            (last - read, write)
+        } else {
+            (write - read, 0)
+        };
+
+        if first + second == 0 {
+            self.read_in_progress.store(false, Ordering::Release);
+            // Only report closed once all data has been drained
+            return Err(if self.closed.load(Ordering::Acquire) {
+                ReadGrantError::Closed
+            } else {
+                ReadGrantError::Empty
+            });
+        }
+
+        Ok((read, first, second))
+    }
+
+    fn release_wrapping(&self, used: usize) {
+        // If there is no grant in progress, return early. This
+        // generally means we are dropping the grant within a
+        // wrapper structure
+        if !self.read_in_progress.load(Ordering::Acquire) {
+            return;
+        }
+
+        // Reader component. `last` does not move while the writer is
+        // inverted, which it must be if we release past `last`
+        let read = self.read.load(Ordering::Acquire);
+        let last = self.last.load(Ordering::Acquire);
+        let new_read = read + used;
+        let new_read = if new_read > last {
+            new_read - last
+        } else {
+            new_read
+        };
+        self.read.store(new_read, Ordering::Release);
+
+        self.read_in_progress.store(false, Ordering::Release);
+    }
 }
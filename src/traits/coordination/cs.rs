@@ -3,7 +3,10 @@
 //! This is provided so bbq2 is usable on bare metal targets that don't
 //! have CAS atomics, like `cortex-m0`/`thumbv6m` targets.
 
-use super::{Coord, ReadGrantError, WriteGrantError};
+use super::{
+    ClearError, Coord, HandleKind, ReadGrantError, Side, Snapshot, TakeError, WriteGrantError,
+    give_back_mask, take_bits,
+};
 use core::{
     cmp::min,
     sync::atomic::{AtomicBool, AtomicUsize, Ordering},
@@ -38,6 +41,12 @@ pub struct CsCoord {
 
     /// Is there an active write grant?
     write_in_progress: AtomicBool,
+
+    /// Has the queue been closed by either side?
+    closed: AtomicBool,
+
+    /// Which kind of handle has been taken for each side, if any
+    taken: AtomicUsize,
 }
 
 impl CsCoord {
@@ -49,7 +58,28 @@ impl CsCoord {
             reserve: AtomicUsize::new(0),
             read_in_progress: AtomicBool::new(false),
             write_in_progress: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            taken: AtomicUsize::new(0),
+        }
+    }
+
+    /// Move the indices of an empty queue back to the start of the buffer
+    ///
+    /// Called by the producer within a critical section, so that a grant that
+    /// only fits at the start of the buffer does not have to wait for the
+    /// consumer to wrap around. Fails if the queue is not empty, or if a read
+    /// grant is live.
+    fn rewind(&self, write: usize, read: usize) -> bool {
+        let last = self.last.load(Ordering::Relaxed);
+        if !(Snapshot { write, read, last }).is_empty()
+            || self.read_in_progress.load(Ordering::Relaxed)
+        {
+            return false;
         }
+        self.write.store(0, Ordering::Relaxed);
+        self.read.store(0, Ordering::Relaxed);
+        self.last.store(0, Ordering::Relaxed);
+        true
     }
 }
 
@@ -63,12 +93,57 @@ unsafe impl Coord for CsCoord {
     #[allow(clippy::declare_interior_mutable_const)]
     const INIT: Self = Self::new();
 
-    fn reset(&self) {
-        // Re-initialize the buffer (not totally needed, but nice to do)
-        self.write.store(0, Ordering::Release);
-        self.read.store(0, Ordering::Release);
-        self.reserve.store(0, Ordering::Release);
-        self.last.store(0, Ordering::Release);
+    fn reset(&self) -> Result<(), ClearError> {
+        critical_section::with(|_cs| {
+            if self.write_in_progress.load(Ordering::Relaxed) {
+                return Err(ClearError::WriteGrantInProgress);
+            }
+            if self.read_in_progress.load(Ordering::Relaxed) {
+                return Err(ClearError::ReadGrantInProgress);
+            }
+
+            // Re-initialize the buffer (not totally needed, but nice to do)
+            self.write.store(0, Ordering::Relaxed);
+            self.read.store(0, Ordering::Relaxed);
+            self.reserve.store(0, Ordering::Relaxed);
+            self.last.store(0, Ordering::Relaxed);
+            Ok(())
+        })
+    }
+
+    fn take(&self, side: Side, kind: HandleKind) -> Result<(), TakeError> {
+        critical_section::with(|_cs| {
+            let taken = take_bits(self.taken.load(Ordering::Relaxed), side, kind)?;
+            self.taken.store(taken, Ordering::Relaxed);
+            Ok(())
+        })
+    }
+
+    fn give_back(&self, side: Side) {
+        critical_section::with(|_cs| {
+            let taken = self.taken.load(Ordering::Relaxed) & give_back_mask(side);
+            self.taken.store(taken, Ordering::Relaxed);
+        })
+    }
+
+    fn close(&self) -> bool {
+        critical_section::with(|_cs| {
+            let was_closed = self.closed.load(Ordering::Relaxed);
+            self.closed.store(true, Ordering::Relaxed);
+            !was_closed
+        })
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        critical_section::with(|_cs| Snapshot {
+            write: self.write.load(Ordering::Relaxed),
+            read: self.read.load(Ordering::Relaxed),
+            last: self.last.load(Ordering::Relaxed),
+        })
     }
 
     fn grant_max_remaining(
@@ -82,6 +157,11 @@ unsafe impl Coord for CsCoord {
             }
             self.write_in_progress.store(true, Ordering::Relaxed);
 
+            if self.closed.load(Ordering::Relaxed) {
+                self.write_in_progress.store(false, Ordering::Relaxed);
+                return Err(WriteGrantError::Closed);
+            }
+
             // Writer component. Must never write to `read`,
             // be careful writing to `load`
             let write = self.write.load(Ordering::Relaxed);
@@ -139,6 +219,16 @@ unsafe impl Coord for CsCoord {
             }
             self.write_in_progress.store(true, Ordering::Relaxed);
 
+            if self.closed.load(Ordering::Relaxed) {
+                self.write_in_progress.store(false, Ordering::Relaxed);
+                return Err(WriteGrantError::Closed);
+            }
+
+            if sz > capacity {
+                self.write_in_progress.store(false, Ordering::Relaxed);
+                return Err(WriteGrantError::TooLarge);
+            }
+
             // Writer component. Must never write to `read`,
             // be careful writing to `load`
             let write = self.write.load(Ordering::Relaxed);
@@ -150,6 +240,9 @@ unsafe impl Coord for CsCoord {
                 if (write + sz) < read {
                     // Inverted, room is still available
                     write
+                } else if self.rewind(write, read) {
+                    // Empty, as the reader has not caught up with the inversion yet
+                    0
                 } else {
                     // Inverted, no room is available
                     self.write_in_progress.store(false, Ordering::Relaxed);
@@ -169,10 +262,13 @@ unsafe impl Coord for CsCoord {
                     if sz < read {
                         // Invertible situation
                         0
+                    } else if self.rewind(write, read) {
+                        // Empty, so the whole buffer is available from the start
+                        0
                     } else {
                         // Not invertible, no space
                         self.write_in_progress.store(false, Ordering::Relaxed);
-                        return Err(WriteGrantError::InsufficientSize);
+                        return Err(WriteGrantError::WouldWrap);
                     }
                 }
             };
@@ -219,7 +315,12 @@ unsafe impl Coord for CsCoord {
 
             if sz == 0 {
                 self.read_in_progress.store(false, Ordering::Relaxed);
-                return Err(ReadGrantError::Empty);
+                // Only report closed once all data has been drained
+                return Err(if self.closed.load(Ordering::Relaxed) {
+                    ReadGrantError::Closed
+                } else {
+                    ReadGrantError::Empty
+                });
             }
 
             Ok((read, sz))
@@ -280,6 +381,36 @@ unsafe impl Coord for CsCoord {
         })
     }
 
+    fn grant_extend(
+        &self,
+        capacity: usize,
+        grant_len: usize,
+        additional: usize,
+    ) -> Result<(), WriteGrantError> {
+        critical_section::with(|_cs| {
+            let reserve = self.reserve.load(Ordering::Relaxed);
+            let start = reserve - grant_len;
+            let read = self.read.load(Ordering::Relaxed);
+            let new_end = reserve
+                .checked_add(additional)
+                .ok_or(WriteGrantError::InsufficientSize)?;
+
+            // If the grant is before the read position, it must never catch up
+            // with read. Otherwise, it may extend up to the end of the buffer.
+            let fits = if start < read {
+                new_end < read
+            } else {
+                new_end <= capacity
+            };
+            if !fits {
+                return Err(WriteGrantError::InsufficientSize);
+            }
+
+            self.reserve.store(new_end, Ordering::Relaxed);
+            Ok(())
+        })
+    }
+
     fn release_inner(&self, used: usize) {
         critical_section::with(|_cs| {
             // If there is no grant in progress, return early. This
@@ -298,4 +429,168 @@ unsafe impl Coord for CsCoord {
             self.read_in_progress.store(false, Ordering::Relaxed);
         })
     }
+
+    fn read_refresh(&self) -> usize {
+        critical_section::with(|_cs| {
+            let write = self.write.load(Ordering::Relaxed);
+            let last = self.last.load(Ordering::Relaxed);
+            let read = self.read.load(Ordering::Relaxed);
+
+            if write < read {
+                // Inverted, only believe last
+                last
+            } else {
+                // Not inverted, only believe write
+                write
+            }
+            .saturating_sub(read)
+        })
+    }
+
+    fn grant_wrapping(&self, capacity: usize, sz: usize) -> Result<usize, WriteGrantError> {
+        critical_section::with(|_cs| {
+            if self.write_in_progress.load(Ordering::Relaxed) {
+                return Err(WriteGrantError::GrantInProgress);
+            }
+
+            if self.closed.load(Ordering::Relaxed) {
+                return Err(WriteGrantError::Closed);
+            }
+
+            if sz > capacity {
+                return Err(WriteGrantError::TooLarge);
+            }
+
+            let write = self.write.load(Ordering::Relaxed);
+            let read = self.read.load(Ordering::Relaxed);
+            let max = capacity;
+
+            let start = if write < read {
+                if (write + sz) < read {
+                    // Inverted, room is still available
+                    write
+                } else if self.rewind(write, read) {
+                    // Empty, as the reader has not caught up with the inversion yet
+                    0
+                } else {
+                    // Inverted, no room is available
+                    return Err(WriteGrantError::InsufficientSize);
+                }
+            } else if write + sz <= max {
+                // Non inverted condition
+                write
+            } else if sz - (max - write) < read {
+                // Continue at the start of the buffer. As with other inversions,
+                // write must never catch up with read.
+                if write == max { 0 } else { write }
+            } else if sz == max && self.rewind(write, read) {
+                // Empty, so the whole buffer is available from the start
+                0
+            } else if sz == max {
+                // The full buffer is only available when write is at the start
+                return Err(WriteGrantError::WouldWrap);
+            } else {
+                return Err(WriteGrantError::InsufficientSize);
+            };
+
+            self.write_in_progress.store(true, Ordering::Relaxed);
+            self.reserve.store(start + sz, Ordering::Relaxed);
+
+            Ok(start)
+        })
+    }
+
+    fn commit_wrapping(&self, capacity: usize, start: usize, used: usize) {
+        critical_section::with(|_cs| {
+            // If there is no grant in progress, return early. This
+            // generally means we are dropping the grant within a
+            // wrapper structure
+            if !self.write_in_progress.load(Ordering::Relaxed) {
+                return;
+            }
+
+            let write = self.write.load(Ordering::Relaxed);
+            let last = self.last.load(Ordering::Relaxed);
+            let max = capacity;
+            let end = start + used;
+
+            let new_write = if end > max {
+                // The committed data continues at the start of the buffer, so the
+                // whole tail is readable
+                self.last.store(max, Ordering::Relaxed);
+                end - max
+            } else {
+                // Same as a contiguous commit, see `commit_inner`
+                if (end < write) && (write != max) {
+                    self.last.store(write, Ordering::Relaxed);
+                } else if end > last {
+                    self.last.store(max, Ordering::Relaxed);
+                }
+                end
+            };
+            self.write.store(new_write, Ordering::Relaxed);
+
+            // Allow subsequent grants
+            self.write_in_progress.store(false, Ordering::Relaxed);
+        })
+    }
+
+    fn read_wrapping(&self) -> Result<(usize, usize, usize), ReadGrantError> {
+        critical_section::with(|_cs| {
+            if self.read_in_progress.load(Ordering::Relaxed) {
+                return Err(ReadGrantError::GrantInProgress);
+            }
+
+            let write = self.write.load(Ordering::Relaxed);
+            let last = self.last.load(Ordering::Relaxed);
+            let mut read = self.read.load(Ordering::Relaxed);
+
+            // Resolve the inverted case or end of read, see `read`
+            if (read == last) && (write < read) {
+                read = 0;
+                self.read.store(0, Ordering::Relaxed);
+            }
+
+            let (first, second) = if write < read {
+                // Inverted, data remains in [read, last) and [0, write)
+                (last - read, write)
+            } else {
+                (write - read, 0)
+            };
+
+            if first + second == 0 {
+                // Only report closed once all data has been drained
+                return Err(if self.closed.load(Ordering::Relaxed) {
+                    ReadGrantError::Closed
+                } else {
+                    ReadGrantError::Empty
+                });
+            }
+
+            self.read_in_progress.store(true, Ordering::Relaxed);
+            Ok((read, first, second))
+        })
+    }
+
+    fn release_wrapping(&self, used: usize) {
+        critical_section::with(|_cs| {
+            // If there is no grant in progress, return early. This
+            // generally means we are dropping the grant within a
+            // wrapper structure
+            if !self.read_in_progress.load(Ordering::Relaxed) {
+                return;
+            }
+
+            let read = self.read.load(Ordering::Relaxed);
+            let last = self.last.load(Ordering::Relaxed);
+            let new_read = read + used;
+            let new_read = if new_read > last {
+                new_read - last
+            } else {
+                new_read
+            };
+            self.read.store(new_read, Ordering::Relaxed);
+            self.read_in_progress.store(false, Ordering::Relaxed);
+        })
+    }
 }
@@ -7,6 +7,9 @@
 //! of coordination.
 //!
 //! The `cas` module is toggled automatically based on `#[cfg(target_has_atomic = "ptr")]`.
+//!
+//! Either coordinator may be wrapped with [`stats::StatsCoord`] to record usage
+//! statistics, such as the peak occupancy of the queue.
 
 #[cfg(target_has_atomic = "ptr")]
 pub mod cas;
@@ -14,6 +17,8 @@ pub mod cas;
 #[cfg(feature = "critical-section")]
 pub mod cs;
 
+pub mod stats;
+
 /// Errors associated with obtaining a write grant
 #[derive(PartialEq, Debug)]
 pub enum WriteGrantError {
@@ -21,6 +26,14 @@ pub enum WriteGrantError {
     InsufficientSize,
     /// Unable to create write grant due to existing write grant
     GrantInProgress,
+    /// Unable to create write grant because the queue has been closed
+    Closed,
+    /// The requested grant is larger than the buffer, and can never succeed
+    TooLarge,
+    /// The requested grant does not fit at the end of the buffer, and cannot
+    /// wrap around to the start yet, as the consumer has not released enough
+    /// data there. It may succeed once the consumer has caught up.
+    WouldWrap,
 }
 
 /// Errors associated with obtaining a read grant
@@ -37,6 +50,190 @@ pub enum ReadGrantError {
     /// If you see this error and you are NOT doing that, please report it, as it
     /// is a bug.
     InconsistentFrameHeader,
+    /// The queue has been closed, and all remaining data has been read
+    Closed,
+    /// Fewer than the requested number of contiguous bytes are available yet
+    InsufficientSize,
+    /// The requested number of bytes cannot become contiguous without the
+    /// consumer first releasing the data before the wrap-around point
+    Fragmented,
+    /// The request can never be satisfied, as it is larger than the buffer
+    TooLarge,
+    /// The frame is larger than the provided buffer. It has been kept in the
+    /// queue, so it may be read again with a larger buffer.
+    BufferTooSmall,
+}
+
+/// Errors associated with clearing a queue
+#[derive(PartialEq, Debug)]
+pub enum ClearError {
+    /// Unable to clear the queue due to an existing read grant
+    ReadGrantInProgress,
+    /// Unable to clear the queue due to an existing write grant
+    WriteGrantInProgress,
+}
+
+/// Errors associated with taking a tracked producer or consumer handle
+#[derive(PartialEq, Debug)]
+pub enum TakeError {
+    /// The handle for this side of the queue has already been taken
+    AlreadyTaken,
+    /// The other side of the queue has been taken as an incompatible kind of
+    /// handle, e.g. a stream producer was taken and a framed consumer requested
+    KindMismatch,
+}
+
+/// One side of a queue
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Side {
+    Producer,
+    Consumer,
+}
+
+/// The kind of handle taken for one side of a queue
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum HandleKind {
+    /// A stream producer or consumer
+    Stream,
+    /// A framed producer or consumer, with a header of `hdr_len` bytes
+    Framed { hdr_len: u8 },
+    /// A wrapping framed producer or consumer, with a header of `hdr_len` bytes
+    WrappingFramed { hdr_len: u8 },
+}
+
+impl HandleKind {
+    /// The kind for framed handles using the header `H`
+    pub const fn framed<H>() -> Self {
+        HandleKind::Framed {
+            hdr_len: core::mem::size_of::<H>() as u8,
+        }
+    }
+
+    /// The kind for wrapping framed handles using the header `H`
+    pub const fn wrapping_framed<H>() -> Self {
+        HandleKind::WrappingFramed {
+            hdr_len: core::mem::size_of::<H>() as u8,
+        }
+    }
+
+    /// Encode the kind into a nonzero byte, zero means "not taken"
+    const fn to_bits(self) -> u8 {
+        match self {
+            HandleKind::Stream => 0x01,
+            HandleKind::Framed { hdr_len } => 0x10 | (hdr_len & 0x0F),
+            HandleKind::WrappingFramed { hdr_len } => 0x20 | (hdr_len & 0x0F),
+        }
+    }
+}
+
+impl Side {
+    const fn shift(self) -> u32 {
+        match self {
+            Side::Producer => 0,
+            Side::Consumer => 8,
+        }
+    }
+}
+
+/// Helper for coordinators, marks `side` as taken in the packed `taken` value
+pub(crate) fn take_bits(taken: usize, side: Side, kind: HandleKind) -> Result<usize, TakeError> {
+    let other = match side {
+        Side::Producer => Side::Consumer,
+        Side::Consumer => Side::Producer,
+    };
+    let mine = (taken >> side.shift()) as u8;
+    let theirs = (taken >> other.shift()) as u8;
+    let kind = kind.to_bits();
+
+    if mine != 0 {
+        Err(TakeError::AlreadyTaken)
+    } else if theirs != 0 && theirs != kind {
+        Err(TakeError::KindMismatch)
+    } else {
+        Ok(taken | ((kind as usize) << side.shift()))
+    }
+}
+
+/// Helper for coordinators, the mask that clears `side` in the packed `taken` value
+pub(crate) const fn give_back_mask(side: Side) -> usize {
+    !(0xFF << side.shift())
+}
+
+/// A snapshot of the indices of a coordinator
+///
+/// Obtained with [`Coord::snapshot`], this can be used to reason about the
+/// occupancy of the queue at a single point in time. If the other side of the
+/// queue is active, the snapshot may already be outdated when it is inspected.
+///
+/// The queries never overflow, even for a snapshot taken by a third handle
+/// while both sides are moving their indices.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Snapshot {
+    /// Where the next byte will be written
+    pub(crate) write: usize,
+    /// Where the next byte will be read from
+    pub(crate) read: usize,
+    /// The end of the readable region when inverted
+    pub(crate) last: usize,
+}
+
+impl Snapshot {
+    /// The number of committed bytes waiting to be released by the consumer
+    pub fn len(&self) -> usize {
+        if self.write < self.read {
+            // Inverted, data remains in [read, last) and [0, write)
+            self.last.saturating_sub(self.read) + self.write
+        } else {
+            self.write - self.read
+        }
+    }
+
+    /// Is there no committed data waiting to be released by the consumer?
+    pub fn is_empty(&self) -> bool {
+        if self.write < self.read {
+            // Inverted, data remains in [read, last) and [0, write)
+            self.read == self.last && self.write == 0
+        } else {
+            self.write == self.read
+        }
+    }
+
+    /// The largest contiguous read grant that could be obtained
+    pub fn max_read_len(&self) -> usize {
+        if self.write < self.read {
+            if self.read == self.last {
+                // The reader will wrap around to the start on the next read
+                self.write
+            } else {
+                self.last.saturating_sub(self.read)
+            }
+        } else {
+            self.write - self.read
+        }
+    }
+
+    /// The largest exact write grant that could be obtained, with a buffer of
+    /// the given `capacity`
+    pub fn max_grant_len(&self, capacity: usize) -> usize {
+        if self.write < self.read {
+            // Inverted, write must never catch up with read
+            self.read - self.write - 1
+        } else {
+            // Either the tail of the buffer, or the start of the buffer if we
+            // wrap around. Again, write must never catch up with read.
+            (capacity - self.write).max(self.read.saturating_sub(1))
+        }
+    }
+
+    /// The total number of bytes that could be written with a grant at the end
+    /// of the buffer, followed by a grant at the start after wrapping around
+    pub fn max_push_len(&self, capacity: usize) -> usize {
+        if self.write < self.read {
+            self.read - self.write - 1
+        } else {
+            (capacity - self.write) + self.read.saturating_sub(1)
+        }
+    }
 }
 
 /// Coordination Handler
@@ -49,8 +246,42 @@ pub enum ReadGrantError {
 pub unsafe trait Coord {
     const INIT: Self;
 
-    // Reset all EXCEPT taken values back to the initial empty state
-    fn reset(&self);
+    /// Reset the indices back to the initial empty state, discarding all data
+    ///
+    /// Fails if a read or write grant is live. The closed and taken states are retained.
+    fn reset(&self) -> Result<(), ClearError>;
+
+    // Taking handles
+
+    /// Mark one side of the queue as taken by the given kind of handle
+    ///
+    /// Fails if that side is already taken, or if the other side was taken
+    /// by an incompatible kind of handle.
+    fn take(&self, side: Side, kind: HandleKind) -> Result<(), TakeError>;
+
+    /// Mark one side of the queue as no longer taken
+    fn give_back(&self, side: Side);
+
+    // Closing
+
+    /// Mark the queue as closed. Returns `false` if it was already closed.
+    ///
+    /// Once closed, write grants fail with [`WriteGrantError::Closed`], and read
+    /// grants fail with [`ReadGrantError::Closed`] once the queue is empty.
+    fn close(&self) -> bool;
+    fn is_closed(&self) -> bool;
+
+    // Queries
+
+    /// Take a snapshot of the current indices
+    ///
+    /// While one side holds no grant, the other side only moves the indices in
+    /// its favour: the consumer only frees space, and the producer only commits
+    /// data. A producer or consumer may therefore rely on the space or data in
+    /// a snapshot still being available for its next grants, as long as it is
+    /// the only handle on its side of the queue, e.g. a tracked handle. Another
+    /// untracked handle on the same side may use it up in the meantime.
+    fn snapshot(&self) -> Snapshot;
 
     // Write Grants
 
@@ -62,8 +293,67 @@ pub unsafe trait Coord {
     fn grant_exact(&self, capacity: usize, sz: usize) -> Result<usize, WriteGrantError>;
     fn commit_inner(&self, capacity: usize, grant_len: usize, used: usize);
 
+    /// Extend the live write grant of `grant_len` bytes by exactly `additional` bytes
+    ///
+    /// Fails with [`WriteGrantError::InsufficientSize`] if the space directly
+    /// after the grant is not free.
+    fn grant_extend(
+        &self,
+        capacity: usize,
+        grant_len: usize,
+        additional: usize,
+    ) -> Result<(), WriteGrantError>;
+
     // Read Grants
 
     fn read(&self) -> Result<(usize, usize), ReadGrantError>;
     fn release_inner(&self, used: usize);
+
+    /// The current length of the contiguous readable region starting at the
+    /// live read grant, which may have grown since the grant was obtained
+    fn read_refresh(&self) -> usize;
+
+    // Wrapping Grants
+    //
+    // These are used by handles that allow a grant to continue at the start of
+    // the buffer, instead of skipping the tail of the buffer. They must not be
+    // mixed with the contiguous grants above.
+
+    /// Obtain a write grant of exactly `sz` bytes, returning its start
+    ///
+    /// If `start + sz` exceeds the `capacity`, the grant continues at the start of
+    /// the buffer.
+    fn grant_wrapping(&self, capacity: usize, sz: usize) -> Result<usize, WriteGrantError>;
+
+    /// Commit the first `used` bytes of a grant obtained with [`Coord::grant_wrapping`]
+    fn commit_wrapping(&self, capacity: usize, start: usize, used: usize);
+
+    /// Obtain a read grant, which may continue at the start of the buffer
+    ///
+    /// Returns the start of the grant, the number of bytes readable before the
+    /// end of the buffer, and the number of bytes readable at the start of the
+    /// buffer after that.
+    fn read_wrapping(&self) -> Result<(usize, usize, usize), ReadGrantError>;
+
+    /// Release the first `used` bytes of a grant obtained with [`Coord::read_wrapping`]
+    fn release_wrapping(&self, used: usize);
+}
+
+#[cfg(test)]
+mod test {
+    use super::Snapshot;
+
+    #[test]
+    fn stale_snapshot() {
+        // A `last` from before the producer wrapped around, as seen by a
+        // handle that raced with both sides
+        let snap = Snapshot {
+            write: 2,
+            read: 12,
+            last: 8,
+        };
+        assert_eq!(snap.len(), 2);
+        assert_eq!(snap.max_read_len(), 0);
+        assert!(!snap.is_empty());
+    }
 }
@@ -0,0 +1,412 @@
+//! Statistics gathering coordination
+//!
+//! [`StatsCoord`] wraps any other coordinator, and records statistics about
+//! how the queue is used. This is useful for sizing buffers, for example by
+//! observing the peak occupancy of a queue under real world load.
+//!
+//! Queues that don't use the wrapper don't pay any cost for it.
+
+use super::{
+    ClearError, Coord, HandleKind, ReadGrantError, Side, Snapshot, TakeError, WriteGrantError,
+};
+use crate::{
+    prod_cons::{
+        framed::{FramedConsumer, FramedProducer, LenHeader},
+        stream::{StreamConsumer, StreamProducer},
+        wrapping::{WrappingConsumer, WrappingProducer},
+    },
+    queue::BBQueue,
+    traits::bbqhdl::BbqHandle,
+};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Coordination that records statistics, wrapping another coordinator `C`
+///
+/// Counters are only ever loaded and stored, so this can be used on targets
+/// without CAS atomics. Each counter is only updated by one side of the queue,
+/// so counts are exact, except for counters updated concurrently by multiple
+/// handles on the same side, or while [`StatsCoord::reset_stats`] is running.
+pub struct StatsCoord<C> {
+    inner: C,
+
+    /// Largest number of committed bytes observed after a commit
+    peak_len: AtomicUsize,
+
+    /// Bytes committed by the producer
+    bytes_committed: AtomicUsize,
+
+    /// Bytes released by the consumer
+    bytes_released: AtomicUsize,
+
+    /// Non-empty commits made by the producer
+    commits: AtomicUsize,
+
+    /// Non-empty releases made by the consumer
+    releases: AtomicUsize,
+
+    /// Write grants refused due to insufficient space
+    insufficient_size: AtomicUsize,
+
+    /// Write grants refused due to an existing grant
+    write_grant_in_progress: AtomicUsize,
+
+    /// Read grants refused due to an existing grant
+    read_grant_in_progress: AtomicUsize,
+
+    /// Bytes skipped at the end of the buffer when a write grant wrapped around
+    wrap_skipped: AtomicUsize,
+
+    /// Is a write grant live? Commits without one are ignored by `inner`
+    write_granted: AtomicBool,
+
+    /// Is a read grant live? Releases without one are ignored by `inner`
+    read_granted: AtomicBool,
+}
+
+/// A copy of the statistics recorded by a [`StatsCoord`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// The largest number of committed, unreleased bytes observed
+    pub peak_len: usize,
+    /// The total number of bytes committed by the producer
+    pub bytes_committed: usize,
+    /// The total number of bytes released by the consumer
+    pub bytes_released: usize,
+    /// The number of non-empty commits. For framed queues, the number of frames
+    pub commits: usize,
+    /// The number of non-empty releases. For framed queues, the number of frames
+    pub releases: usize,
+    /// The number of write grants refused for lack of space, with
+    /// [`WriteGrantError::InsufficientSize`] or [`WriteGrantError::WouldWrap`]
+    pub insufficient_size: usize,
+    /// The number of write grants refused with `GrantInProgress`
+    pub write_grant_in_progress: usize,
+    /// The number of read grants refused with `GrantInProgress`
+    pub read_grant_in_progress: usize,
+    /// The number of bytes skipped at the end of the buffer when wrapping around
+    pub wrap_skipped: usize,
+}
+
+impl<C: Coord> StatsCoord<C> {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicUsize = AtomicUsize::new(0);
+
+    pub const fn new() -> Self {
+        Self {
+            inner: C::INIT,
+            peak_len: Self::ZERO,
+            bytes_committed: Self::ZERO,
+            bytes_released: Self::ZERO,
+            commits: Self::ZERO,
+            releases: Self::ZERO,
+            insufficient_size: Self::ZERO,
+            write_grant_in_progress: Self::ZERO,
+            read_grant_in_progress: Self::ZERO,
+            wrap_skipped: Self::ZERO,
+            write_granted: AtomicBool::new(false),
+            read_granted: AtomicBool::new(false),
+        }
+    }
+
+    fn granted<T, E>(flag: &AtomicBool, res: Result<T, E>) -> Result<T, E> {
+        if res.is_ok() {
+            flag.store(true, Ordering::Relaxed);
+        }
+        res
+    }
+
+    /// Mark the grant as finished, returning whether it was live
+    fn finish(flag: &AtomicBool) -> bool {
+        // Only load/store, see the docs on `StatsCoord`
+        let live = flag.load(Ordering::Relaxed);
+        flag.store(false, Ordering::Relaxed);
+        live
+    }
+
+    fn count_commit(&self, used: usize) {
+        Self::bump(&self.bytes_committed, used);
+        Self::bump(&self.commits, 1);
+        let len = self.inner.snapshot().len();
+        if len > self.peak_len.load(Ordering::Relaxed) {
+            self.peak_len.store(len, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<C: Coord> Default for StatsCoord<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> StatsCoord<C> {
+    /// Obtain a copy of the current statistics
+    pub fn stats(&self) -> Stats {
+        Stats {
+            peak_len: self.peak_len.load(Ordering::Relaxed),
+            bytes_committed: self.bytes_committed.load(Ordering::Relaxed),
+            bytes_released: self.bytes_released.load(Ordering::Relaxed),
+            commits: self.commits.load(Ordering::Relaxed),
+            releases: self.releases.load(Ordering::Relaxed),
+            insufficient_size: self.insufficient_size.load(Ordering::Relaxed),
+            write_grant_in_progress: self.write_grant_in_progress.load(Ordering::Relaxed),
+            read_grant_in_progress: self.read_grant_in_progress.load(Ordering::Relaxed),
+            wrap_skipped: self.wrap_skipped.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Reset all statistics back to zero
+    pub fn reset_stats(&self) {
+        for ctr in [
+            &self.peak_len,
+            &self.bytes_committed,
+            &self.bytes_released,
+            &self.commits,
+            &self.releases,
+            &self.insufficient_size,
+            &self.write_grant_in_progress,
+            &self.read_grant_in_progress,
+            &self.wrap_skipped,
+        ] {
+            ctr.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn bump(ctr: &AtomicUsize, amt: usize) {
+        // Only load/store, see the docs on `StatsCoord`
+        let old = ctr.load(Ordering::Relaxed);
+        ctr.store(old.wrapping_add(amt), Ordering::Relaxed);
+    }
+
+    fn count_write_err<T>(&self, res: Result<T, WriteGrantError>) -> Result<T, WriteGrantError> {
+        match res {
+            Err(WriteGrantError::InsufficientSize | WriteGrantError::WouldWrap) => {
+                Self::bump(&self.insufficient_size, 1)
+            }
+            Err(WriteGrantError::GrantInProgress) => Self::bump(&self.write_grant_in_progress, 1),
+            _ => {}
+        }
+        res
+    }
+}
+
+unsafe impl<C: Coord> Coord for StatsCoord<C> {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = Self::new();
+
+    fn reset(&self) -> Result<(), ClearError> {
+        self.inner.reset()
+    }
+
+    fn take(&self, side: Side, kind: HandleKind) -> Result<(), TakeError> {
+        self.inner.take(side, kind)
+    }
+
+    fn give_back(&self, side: Side) {
+        self.inner.give_back(side)
+    }
+
+    fn close(&self) -> bool {
+        self.inner.close()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        self.inner.snapshot()
+    }
+
+    fn grant_max_remaining(
+        &self,
+        capacity: usize,
+        sz: usize,
+    ) -> Result<(usize, usize), WriteGrantError> {
+        let res = self.inner.grant_max_remaining(capacity, sz);
+        self.count_write_err(Self::granted(&self.write_granted, res))
+    }
+
+    fn grant_exact(&self, capacity: usize, sz: usize) -> Result<usize, WriteGrantError> {
+        let res = self.inner.grant_exact(capacity, sz);
+        self.count_write_err(Self::granted(&self.write_granted, res))
+    }
+
+    fn commit_inner(&self, capacity: usize, grant_len: usize, used: usize) {
+        // Only the producer moves `write`. The tail of the buffer is skipped when
+        // the commit moves `write` back to the start, which also happens when a
+        // wrapped grant is committed with zero bytes.
+        let old_write = self.inner.snapshot().write;
+        self.inner.commit_inner(capacity, grant_len, used);
+        if !Self::finish(&self.write_granted) {
+            return;
+        }
+        let new_write = self.inner.snapshot().write;
+        if new_write < old_write {
+            Self::bump(&self.wrap_skipped, capacity - old_write);
+        }
+        let used = used.min(grant_len);
+        if used != 0 {
+            self.count_commit(used);
+        }
+    }
+
+    fn grant_extend(
+        &self,
+        capacity: usize,
+        grant_len: usize,
+        additional: usize,
+    ) -> Result<(), WriteGrantError> {
+        self.count_write_err(self.inner.grant_extend(capacity, grant_len, additional))
+    }
+
+    fn read(&self) -> Result<(usize, usize), ReadGrantError> {
+        let res = Self::granted(&self.read_granted, self.inner.read());
+        if res == Err(ReadGrantError::GrantInProgress) {
+            Self::bump(&self.read_grant_in_progress, 1);
+        }
+        res
+    }
+
+    fn release_inner(&self, used: usize) {
+        self.inner.release_inner(used);
+        if Self::finish(&self.read_granted) && used != 0 {
+            Self::bump(&self.bytes_released, used);
+            Self::bump(&self.releases, 1);
+        }
+    }
+
+    fn read_refresh(&self) -> usize {
+        self.inner.read_refresh()
+    }
+
+    fn grant_wrapping(&self, capacity: usize, sz: usize) -> Result<usize, WriteGrantError> {
+        let res = self.inner.grant_wrapping(capacity, sz);
+        self.count_write_err(Self::granted(&self.write_granted, res))
+    }
+
+    fn commit_wrapping(&self, capacity: usize, start: usize, used: usize) {
+        self.inner.commit_wrapping(capacity, start, used);
+        if Self::finish(&self.write_granted) && used != 0 {
+            self.count_commit(used);
+        }
+    }
+
+    fn read_wrapping(&self) -> Result<(usize, usize, usize), ReadGrantError> {
+        let res = Self::granted(&self.read_granted, self.inner.read_wrapping());
+        if res == Err(ReadGrantError::GrantInProgress) {
+            Self::bump(&self.read_grant_in_progress, 1);
+        }
+        res
+    }
+
+    fn release_wrapping(&self, used: usize) {
+        self.inner.release_wrapping(used);
+        if Self::finish(&self.read_granted) && used != 0 {
+            Self::bump(&self.bytes_released, used);
+            Self::bump(&self.releases, 1);
+        }
+    }
+}
+
+// ---- Access from queues and handles ----
+
+macro_rules! impl_stats_access {
+    ($($handle:ident$(<$h:ident>)?),*) => {
+        $(
+            impl<Q, C $(, $h)?> $handle<Q $(, $h)?>
+            where
+                Q: BbqHandle<Coord = StatsCoord<C>>,
+                C: Coord,
+                $($h: LenHeader,)?
+            {
+                /// Obtain a copy of the statistics of the queue
+                pub fn stats(&self) -> Stats {
+                    self.bbq.cor.stats()
+                }
+
+                /// Reset the statistics of the queue back to zero
+                pub fn reset_stats(&self) {
+                    self.bbq.cor.reset_stats()
+                }
+            }
+        )*
+    };
+}
+
+impl_stats_access!(
+    StreamProducer,
+    StreamConsumer,
+    FramedProducer<H>,
+    FramedConsumer<H>,
+    WrappingProducer<H>,
+    WrappingConsumer<H>
+);
+
+impl<S, C, N, M> BBQueue<S, StatsCoord<C>, N, M> {
+    /// Obtain a copy of the statistics of the queue
+    pub fn stats(&self) -> Stats {
+        self.cor.stats()
+    }
+
+    /// Reset the statistics of the queue back to zero
+    pub fn reset_stats(&self) {
+        self.cor.reset_stats()
+    }
+}
+
+#[cfg(all(test, target_has_atomic = "ptr"))]
+mod test {
+    use super::*;
+    use crate::traits::{
+        coordination::cas::AtomicCoord, notifier::blocking::Blocking, storage::Inline,
+    };
+
+    #[test]
+    fn counts() {
+        static BBQ: BBQueue<Inline<16>, StatsCoord<AtomicCoord>, Blocking> = BBQueue::new();
+        let prod = BBQ.stream_producer();
+        let cons = BBQ.stream_consumer();
+
+        let wgr = prod.grant_exact(10).unwrap();
+        assert_eq!(
+            prod.grant_exact(1).err(),
+            Some(WriteGrantError::GrantInProgress)
+        );
+        wgr.commit(10);
+        assert_eq!(prod.grant_exact(8).err(), Some(WriteGrantError::WouldWrap));
+        let rgr = cons.read().unwrap();
+        assert_eq!(cons.read().err(), Some(ReadGrantError::GrantInProgress));
+        rgr.release(9);
+
+        // The tail is too short, so this wraps around and skips six bytes, which
+        // are only counted once the grant is committed
+        let wgr = prod.grant_exact(8).unwrap();
+        assert_eq!(prod.stats().wrap_skipped, 0);
+        wgr.commit(4);
+
+        assert_eq!(
+            cons.stats(),
+            Stats {
+                peak_len: 10,
+                bytes_committed: 14,
+                bytes_released: 9,
+                commits: 2,
+                releases: 1,
+                insufficient_size: 1,
+                write_grant_in_progress: 1,
+                read_grant_in_progress: 1,
+                wrap_skipped: 6,
+            }
+        );
+
+        prod.reset_stats();
+        assert_eq!(BBQ.stats(), Stats::default());
+
+        // Finishing a grant that is not live is ignored by the coordinator, and
+        // must not be counted either
+        BBQ.cor.commit_inner(16, 4, 4);
+        BBQ.cor.release_inner(4);
+        assert_eq!(BBQ.stats(), Stats::default());
+    }
+}
@@ -1,4 +1,5 @@
 pub mod bbqhdl;
 pub mod coordination;
+pub mod mode;
 pub mod notifier;
 pub mod storage;
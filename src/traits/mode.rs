@@ -0,0 +1,77 @@
+//! "Mode" functionality
+//!
+//! By default, a [`BBQueue`](crate::queue::BBQueue) can hand out any kind of
+//! producer or consumer, in [`AnyMode`]. It is then up to the user not to mix
+//! stream and framed handles, or framed handles with different headers.
+//!
+//! Optionally, a queue may be fixed to a single mode at the type level, using
+//! [`Stream`], [`Framed`], or [`WrappingFramed`] as the last generic parameter
+//! of the queue. Only the matching producer and consumer constructors exist for
+//! such a queue, so any mixing is caught at compile time.
+
+use core::marker::PhantomData;
+
+use crate::prod_cons::framed::LenHeader;
+
+/// A queue mode that allows any kind of producer and consumer (the default)
+///
+/// Framed handles created with the methods of `BBQueue` use a `u16` header.
+pub struct AnyMode;
+
+/// A queue mode that only allows stream producers and consumers
+pub struct Stream;
+
+/// A queue mode that only allows framed producers and consumers, with the header `H`
+pub struct Framed<H: LenHeader = u16> {
+    _pd: PhantomData<H>,
+}
+
+/// A queue mode that only allows wrapping framed producers and consumers, with
+/// the header `H`
+pub struct WrappingFramed<H: LenHeader = u16> {
+    _pd: PhantomData<H>,
+}
+
+/// Modes that allow stream producers and consumers
+pub trait StreamMode {}
+
+/// Modes that allow framed producers and consumers
+pub trait FramedMode: AllowsHeader<Self::Header> {
+    /// The header used by framed handles created by the queue
+    type Header: LenHeader;
+}
+
+/// Modes that allow framed producers and consumers with the header `H`
+pub trait AllowsHeader<H: LenHeader> {}
+
+/// Modes that allow wrapping framed producers and consumers
+pub trait WrappingMode: AllowsWrapping<Self::Header> {
+    /// The header used by wrapping framed handles created by the queue
+    type Header: LenHeader;
+}
+
+/// Modes that allow wrapping framed producers and consumers with the header `H`
+pub trait AllowsWrapping<H: LenHeader> {}
+
+impl StreamMode for AnyMode {}
+impl StreamMode for Stream {}
+
+impl FramedMode for AnyMode {
+    type Header = u16;
+}
+impl<H: LenHeader> FramedMode for Framed<H> {
+    type Header = H;
+}
+
+impl<H: LenHeader> AllowsHeader<H> for AnyMode {}
+impl<H: LenHeader> AllowsHeader<H> for Framed<H> {}
+
+impl WrappingMode for AnyMode {
+    type Header = u16;
+}
+impl<H: LenHeader> WrappingMode for WrappingFramed<H> {
+    type Header = H;
+}
+
+impl<H: LenHeader> AllowsWrapping<H> for AnyMode {}
+impl<H: LenHeader> AllowsWrapping<H> for WrappingFramed<H> {}
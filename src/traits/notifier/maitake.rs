@@ -1,5 +1,5 @@
 use const_init::ConstInit;
-use maitake_sync::WaitCell;
+use maitake_sync::{WaitCell, WaitQueue};
 
 use super::{AsyncNotifier, Notifier};
 
@@ -42,6 +42,8 @@ impl Notifier for MaiNotSpsc {
     }
 }
 
+// NOTE: the cells and queues below are never closed, closing the bbqueue is
+// tracked by the coordinator instead, so waiting can never fail.
 impl AsyncNotifier for MaiNotSpsc {
     async fn wait_for_not_empty<T, F: FnMut() -> Option<T>>(&self, f: F) -> T {
         self.not_empty.wait_for_value(f).await.unwrap()
@@ -51,3 +53,127 @@ impl AsyncNotifier for MaiNotSpsc {
         self.not_full.wait_for_value(f).await.unwrap()
     }
 }
+
+/// A Maitake-Sync based notifier that supports multiple waiters per side
+///
+/// Usable for async context. Unlike [`MaiNotSpsc`], any number of tasks may be
+/// waiting for a read grant or a write grant at the same time, for example when
+/// handles are shared between tasks.
+///
+/// When `WAKE_ALL` is `false`, each notification wakes a single waiting task,
+/// and the remaining tasks are woken by later notifications. When `WAKE_ALL` is
+/// `true`, each notification wakes every waiting task on that side, and the
+/// tasks that fail to obtain a grant go back to waiting.
+///
+/// See [`MaiNotWakeOne`] and [`MaiNotWakeAll`] for the two configurations.
+pub struct MaiNotMulti<const WAKE_ALL: bool> {
+    not_empty: WaitQueue,
+    not_full: WaitQueue,
+}
+
+/// A [`MaiNotMulti`] that wakes one waiting task per notification
+pub type MaiNotWakeOne = MaiNotMulti<false>;
+
+/// A [`MaiNotMulti`] that wakes all waiting tasks on each notification
+pub type MaiNotWakeAll = MaiNotMulti<true>;
+
+impl<const WAKE_ALL: bool> MaiNotMulti<WAKE_ALL> {
+    pub fn new() -> Self {
+        Self::INIT
+    }
+
+    fn wake(queue: &WaitQueue) {
+        if WAKE_ALL {
+            queue.wake_all();
+        } else {
+            queue.wake();
+        }
+    }
+}
+
+impl<const WAKE_ALL: bool> Default for MaiNotMulti<WAKE_ALL> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const WAKE_ALL: bool> ConstInit for MaiNotMulti<WAKE_ALL> {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = Self {
+        not_empty: WaitQueue::new(),
+        not_full: WaitQueue::new(),
+    };
+}
+
+impl<const WAKE_ALL: bool> Notifier for MaiNotMulti<WAKE_ALL> {
+    fn wake_one_consumer(&self) {
+        Self::wake(&self.not_empty);
+    }
+
+    fn wake_one_producer(&self) {
+        Self::wake(&self.not_full);
+    }
+
+    fn wake_all(&self) {
+        self.not_empty.wake_all();
+        self.not_full.wake_all();
+    }
+}
+
+impl<const WAKE_ALL: bool> AsyncNotifier for MaiNotMulti<WAKE_ALL> {
+    async fn wait_for_not_empty<T, F: FnMut() -> Option<T>>(&self, f: F) -> T {
+        self.not_empty.wait_for_value(f).await.unwrap()
+    }
+
+    async fn wait_for_not_full<T, F: FnMut() -> Option<T>>(&self, f: F) -> T {
+        self.not_full.wait_for_value(f).await.unwrap()
+    }
+}
+
+#[cfg(all(test, target_has_atomic = "ptr"))]
+mod test {
+    use core::time::Duration;
+
+    use super::*;
+    use crate::{
+        queue::BBQueue,
+        traits::{coordination::cas::AtomicCoord, storage::Inline},
+    };
+
+    macro_rules! multi_waiters_test {
+        ($name:ident, $not:ty) => {
+            #[tokio::test]
+            async fn $name() {
+                static BBQ: BBQueue<Inline<64>, AtomicCoord, $not> = BBQueue::new();
+                let prod = BBQ.stream_producer();
+
+                // Two tasks waiting on the same side at the same time
+                let rxfuts = [0u8, 1].map(|_| {
+                    let cons = BBQ.stream_consumer();
+                    tokio::task::spawn(async move {
+                        let rgr = cons.wait_read().await.unwrap();
+                        assert_eq!(rgr.len(), 1);
+                        rgr.release(1);
+                    })
+                });
+
+                for i in 0..2 {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    let mut wgr = prod.grant_exact(1).unwrap();
+                    wgr[0] = i;
+                    wgr.commit(1);
+                }
+
+                for fut in rxfuts {
+                    tokio::time::timeout(Duration::from_secs(1), fut)
+                        .await
+                        .unwrap()
+                        .unwrap();
+                }
+            }
+        };
+    }
+
+    multi_waiters_test!(multi_waiters_wake_one, MaiNotWakeOne);
+    multi_waiters_test!(multi_waiters_wake_all, MaiNotWakeAll);
+}
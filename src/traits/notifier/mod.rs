@@ -1,6 +1,41 @@
 //! "Notification" functionality
 //!
 //! This functionality allows (or doesn't allow) for awaiting a read/write grant
+//!
+//! ## Choosing a notifier
+//!
+//! The coordinator ([`Coord`](crate::traits::coordination::Coord)) is always
+//! responsible for making sure that at most one read grant and one write grant
+//! are live at any time, regardless of how many handles exist. The notifier
+//! only decides which waiting tasks are woken up, so no combination of notifier
+//! and coordinator can cause memory unsafety. Some combinations can however cause
+//! a waiting task to never be woken:
+//!
+//! | Notifier          | Coordinator | Waiters per side | Notes                                        |
+//! | :---              | :---        | :---             | :---                                         |
+//! | [`Blocking`]      | any         | n/a              | No async waiting, callers poll               |
+//! | [`MaiNotSpsc`]    | any         | one              | A second waiter may never be woken           |
+//! | [`MaiNotWakeOne`] | any         | many             | One waiter is woken per commit/release       |
+//! | [`MaiNotWakeAll`] | any         | many             | All waiters on a side are woken each time    |
+//!
+//! With the multi-waiter notifiers, a woken task that loses the race for the
+//! grant goes back to waiting until the next notification for its side.
+//!
+//! Notifiers are never closed themselves: closing a queue is tracked by the
+//! coordinator, and [`Notifier::wake_all`] is used to let every waiter observe it.
+//!
+//! When notifications are sent from interrupt context, e.g. when using the
+//! [`CsCoord`](crate::traits::coordination::cs::CsCoord) with a producer in an
+//! interrupt, note that [`MaiNotWakeOne`] and [`MaiNotWakeAll`] take a short
+//! lock when waking. On single core targets, enable the `critical-section`
+//! feature of `maitake-sync` so that this lock cannot be interrupted by a task
+//! that would then deadlock on it. [`MaiNotSpsc`] is lock-free and has no such
+//! requirement.
+//!
+//! [`Blocking`]: blocking::Blocking
+//! [`MaiNotSpsc`]: maitake::MaiNotSpsc
+//! [`MaiNotWakeOne`]: maitake::MaiNotWakeOne
+//! [`MaiNotWakeAll`]: maitake::MaiNotWakeAll
 
 use const_init::ConstInit;
 
@@ -13,6 +48,15 @@ pub mod blocking;
 pub trait Notifier: ConstInit {
     fn wake_one_consumer(&self);
     fn wake_one_producer(&self);
+
+    /// Wake every waiting consumer and producer
+    ///
+    /// Used when the state of the queue changes for all waiters at once, for
+    /// example when the queue is closed.
+    fn wake_all(&self) {
+        self.wake_one_consumer();
+        self.wake_one_producer();
+    }
 }
 
 /// Async notifications